@@ -0,0 +1,95 @@
+//! Compares `smart_quote!` with quoting each token by parsing it at runtime,
+//! which is what `smart_quote!` used to do.
+//!
+//! Run with `cargo bench --bench quote`.
+
+use pmutil::prelude::*;
+use proc_macro2::{Delimiter, Ident, Spacing, Span, TokenStream, TokenTree};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const FIELDS: usize = 64;
+const ITERS: u32 = 200;
+
+/// Template of a typical derive macro.
+fn derive(fields: &[Ident]) -> Quote {
+    let ty = Ident::new("Foo", Span::call_site());
+
+    q!(Vars { Type: &ty, field: fields }, {
+        impl ::core::fmt::Debug for Type {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                let mut s = f.debug_struct("Foo");
+                @for (field) {
+                    s.field(stringify!(field), &self.field);
+                }
+                s.finish()
+            }
+        }
+
+        impl ::core::clone::Clone for Type {
+            fn clone(&self) -> Self {
+                Type {
+                    @for (field) sep(,) {
+                        field: ::core::clone::Clone::clone(&self.field)
+                    }
+                }
+            }
+        }
+
+        impl ::core::cmp::PartialEq for Type {
+            fn eq(&self, other: &Self) -> bool {
+                true @for (field) { && self.field == other.field && 0u32 < 1 }
+            }
+        }
+    })
+}
+
+/// Quotes `tts` by parsing each token. Joint punctuations and lifetimes are
+/// parsed at once, like `stringify!` of a template token.
+fn parse_each(q: &mut Quote, tts: TokenStream) {
+    let mut buf = String::new();
+
+    for tt in tts {
+        match tt {
+            TokenTree::Group(g) if g.delimiter() != Delimiter::None => {
+                q.push_group(g.delimiter(), |q| parse_each(q, g.stream()))
+            }
+            TokenTree::Group(g) => parse_each(q, g.stream()),
+            TokenTree::Punct(p) if p.spacing() == Spacing::Joint => buf.push(p.as_char()),
+            tt => {
+                buf.push_str(&tt.to_string());
+                q.push_parsed(&buf);
+                buf.clear();
+            }
+        }
+    }
+}
+
+fn bench<F: FnMut() -> Quote>(name: &str, mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERS {
+        black_box(f());
+    }
+    let elapsed = start.elapsed() / ITERS;
+    println!("{:<12} {:>10?}/iter", name, elapsed);
+    elapsed
+}
+
+fn main() {
+    let fields = (0..FIELDS)
+        .map(|i| Ident::new(&format!("field_{}", i), Span::call_site()))
+        .collect::<Vec<_>>();
+    let tts = TokenStream::from(derive(&fields));
+
+    let direct = bench("direct", || derive(&fields));
+    let parsed = bench("parse_each", || {
+        let mut q = Quote::new_call_site();
+        parse_each(&mut q, tts.clone());
+        q
+    });
+
+    println!(
+        "parse_each is {:.1}x slower",
+        parsed.as_secs_f64() / direct.as_secs_f64()
+    );
+}
@@ -0,0 +1,515 @@
+//! Proc-macro implementation of `pmutil::smart_quote!`.
+//!
+//! Unlike the `macro_rules!` version, this expands the whole template at
+//! once, so it does not hit the recursion limit. Use this via feature
+//! `macros` of `pmutil`.
+
+extern crate proc_macro;
+
+use proc_macro2::{Delimiter, Ident, Literal, Spacing, Span, TokenStream, TokenTree};
+use quote::{quote, quote_spanned, ToTokens};
+use syn::parse::discouraged::Speculative;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{braced, parenthesized, Attribute, Expr, Lit, Token};
+
+/// Proc-macro version of `pmutil::smart_quote!`, which accepts the same
+/// template syntax.
+///
+/// Generated code refers to `::pmutil`, unless the template is prefixed with
+/// a path to the crate like `$crate;`. `pmutil::smart_quote!` passes
+/// `$crate`, so it works even if `pmutil` is renamed or re-exported.
+#[proc_macro]
+pub fn smart_quote(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    match syn::parse::<Input>(input).and_then(|input| input.expand()) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct Input {
+    /// Path to `pmutil`, which is passed as `$crate` by `pmutil::smart_quote!`.
+    krate: TokenStream,
+    span: Option<Expr>,
+    vars: Vec<Var>,
+    body: TokenStream,
+}
+
+struct Var {
+    /// `#[respan]`
+    respan: bool,
+    name: Ident,
+    value: Option<Expr>,
+}
+
+impl Parse for Input {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let krate = crate_path(input)?;
+
+        let span = if is_vars(input) || is_body(input) {
+            None
+        } else {
+            let span = input.parse()?;
+            input.parse::<Token![=>]>()?;
+            Some(span)
+        };
+
+        let vars = if is_vars(input) {
+            input.parse::<Ident>()?;
+            let content;
+            braced!(content in input);
+            let vars = Punctuated::<Var, Token![,]>::parse_terminated(&content)?;
+            input.parse::<Token![,]>()?;
+            vars.into_iter().collect()
+        } else {
+            vec![]
+        };
+
+        let content;
+        if input.peek(syn::token::Paren) {
+            parenthesized!(content in input);
+        } else {
+            braced!(content in input);
+        }
+        let body = content.parse()?;
+
+        Ok(Input {
+            krate,
+            span,
+            vars,
+            body,
+        })
+    }
+}
+
+/// Parses `path;`, or returns `::pmutil` if there's no `;` in `input`.
+fn crate_path(input: ParseStream) -> syn::Result<TokenStream> {
+    let fork = input.fork();
+    let mut path = TokenStream::new();
+
+    while !fork.is_empty() {
+        if fork.peek(Token![;]) {
+            fork.parse::<Token![;]>()?;
+            input.advance_to(&fork);
+            return Ok(path);
+        }
+        path.extend(Some(fork.parse::<TokenTree>()?));
+    }
+
+    Ok(quote!(::pmutil))
+}
+
+/// Returns true if `input` is only the template, like `{ .. }` or `( .. )`.
+/// Otherwise, a group is the start of the span expression, like
+/// `(field.span()) => { .. }`.
+fn is_body(input: ParseStream) -> bool {
+    (input.peek(syn::token::Brace) || input.peek(syn::token::Paren)) && {
+        let fork = input.fork();
+        fork.parse::<TokenTree>().is_ok() && fork.is_empty()
+    }
+}
+
+fn is_vars(input: ParseStream) -> bool {
+    input.peek(syn::Ident) && input.peek2(syn::token::Brace) && {
+        let fork = input.fork();
+        fork.parse::<Ident>().map(|i| i == "Vars").unwrap_or(false)
+    }
+}
+
+impl Parse for Var {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut respan = false;
+        for attr in input.call(Attribute::parse_outer)? {
+            if !attr.path().is_ident("respan") {
+                return Err(syn::Error::new_spanned(attr, "unknown attribute"));
+            }
+            attr.meta.require_path_only()?;
+            respan = true;
+        }
+
+        let name = input.parse()?;
+        let value = if input.peek(Token![:]) {
+            input.parse::<Token![:]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
+        Ok(Var {
+            respan,
+            name,
+            value,
+        })
+    }
+}
+
+impl Input {
+    fn expand(self) -> syn::Result<TokenStream> {
+        let t = tokens_ident();
+        let krate = &self.krate;
+
+        let mut vars = TokenStream::new();
+        for var in &self.vars {
+            let name = &var.name;
+            let value = match var.value {
+                Some(ref value) => value.to_token_stream(),
+                None => name.to_token_stream(),
+            };
+            vars.extend(quote!(
+                #[allow(non_snake_case, unused_variables)]
+                let #name = #value;
+            ));
+        }
+
+        let body = Template {
+            krate,
+            vars: &self.vars,
+        }
+        .quote(self.body)?;
+        let loc = location(krate);
+
+        let closure = quote_spanned!(Span::mixed_site()=>
+            |#t: &mut #krate::Quote| {
+                #vars
+                #t.report_loc(#loc);
+                #body
+            }
+        );
+
+        Ok(match self.span {
+            Some(span) => quote_spanned!(Span::mixed_site()=>
+                |#t: &mut #krate::Quote| {
+                    #t.with_span(#span, #closure);
+                }
+            ),
+            None => closure,
+        })
+    }
+}
+
+/// Identifier of `&mut Quote`, which is hygienic.
+fn tokens_ident() -> Ident {
+    Ident::new("_tokens", Span::mixed_site())
+}
+
+fn location(krate: &TokenStream) -> TokenStream {
+    quote!(#krate::quoter_location!())
+}
+
+struct Template<'a> {
+    krate: &'a TokenStream,
+    vars: &'a [Var],
+}
+
+impl Template<'_> {
+    /// Returns statements which quotes `tokens`.
+    fn quote(&self, tokens: TokenStream) -> syn::Result<TokenStream> {
+        let t = tokens_ident();
+        let krate = self.krate;
+        let tokens = tokens.into_iter().collect::<Vec<_>>();
+        let mut stmts = TokenStream::new();
+
+        let mut i = 0;
+        while i < tokens.len() {
+            if let Some((directive, len)) = self.directive(&tokens[i..])? {
+                stmts.extend(directive);
+                i += len;
+                continue;
+            }
+
+            match tokens[i] {
+                TokenTree::Group(ref g) => {
+                    let delim = match g.delimiter() {
+                        Delimiter::Parenthesis => quote!(Parenthesis),
+                        Delimiter::Brace => quote!(Brace),
+                        Delimiter::Bracket => quote!(Bracket),
+                        Delimiter::None => {
+                            stmts.extend(self.quote(g.stream())?);
+                            i += 1;
+                            continue;
+                        }
+                    };
+                    let child = self.closure(g.stream())?;
+                    stmts.extend(quote!(
+                        #t.push_group(#krate::proc_macro2::Delimiter::#delim, #child);
+                    ));
+                }
+                TokenTree::Ident(ref ident) => match self.vars.iter().find(|v| v.name == *ident) {
+                    Some(Var { respan: true, .. }) => {
+                        stmts.extend(quote!(#t.push_tokens_respanned(&#ident);));
+                    }
+                    Some(..) => stmts.extend(quote!(#t.push_tokens(&#ident);)),
+                    None => stmts.extend(push_token(krate, &tokens[i])),
+                },
+                ref tt => stmts.extend(push_token(krate, tt)),
+            }
+            i += 1;
+        }
+
+        Ok(stmts)
+    }
+
+    /// Returns a closure which quotes `tokens`.
+    fn closure(&self, tokens: TokenStream) -> syn::Result<TokenStream> {
+        let t = tokens_ident();
+        let krate = self.krate;
+        let loc = location(krate);
+        let body = self.quote(tokens)?;
+
+        Ok(quote_spanned!(Span::mixed_site()=>
+            |#t: &mut #krate::Quote| {
+                #t.report_loc(#loc);
+                #body
+            }
+        ))
+    }
+
+    /// Handles directives starting with `@`. Returns statements and number of
+    /// consumed tokens.
+    fn directive(&self, tokens: &[TokenTree]) -> syn::Result<Option<(TokenStream, usize)>> {
+        let t = tokens_ident();
+        let krate = self.krate;
+        let loc = location(krate);
+
+        match tokens.first() {
+            Some(TokenTree::Punct(p)) if p.as_char() == '@' => {}
+            _ => return Ok(None),
+        }
+
+        // `@{ expr }`
+        if let Some(expr) = group(tokens.get(1), Delimiter::Brace) {
+            return Ok(Some((quote!(#t.push_tokens(&{ #expr });), 2)));
+        }
+
+        let name = match tokens.get(1) {
+            Some(TokenTree::Ident(name)) => name.to_string(),
+            _ => return Ok(None),
+        };
+
+        match &*name {
+            "raw" => {
+                let inner = match group(tokens.get(2), Delimiter::Parenthesis) {
+                    Some(inner) => inner,
+                    None => return Ok(None),
+                };
+                let raw = Template { krate, vars: &[] }.quote_raw(inner);
+                Ok(Some((raw, 3)))
+            }
+
+            "span" => {
+                let (span, body) = match (
+                    group(tokens.get(2), Delimiter::Parenthesis),
+                    group(tokens.get(3), Delimiter::Brace),
+                ) {
+                    (Some(span), Some(body)) => (span, body),
+                    _ => return Ok(None),
+                };
+                let child = self.closure(body)?;
+                Ok(Some((quote!(#t.with_span(#span, #child);), 4)))
+            }
+
+            "if" => {
+                let (cond, then) = match (
+                    group(tokens.get(2), Delimiter::Parenthesis),
+                    group(tokens.get(3), Delimiter::Brace),
+                ) {
+                    (Some(cond), Some(then)) => (cond, then),
+                    _ => return Ok(None),
+                };
+                let then = self.quote(then)?;
+
+                let is_else = match (tokens.get(4), tokens.get(5)) {
+                    (Some(TokenTree::Punct(p)), Some(TokenTree::Ident(i))) => {
+                        p.as_char() == '@' && i == "else"
+                    }
+                    _ => false,
+                };
+                if let (true, Some(els)) = (is_else, group(tokens.get(6), Delimiter::Brace)) {
+                    let els = self.quote(els)?;
+                    return Ok(Some((
+                        quote!(
+                            if #cond {
+                                #t.report_loc(#loc);
+                                #then
+                            } else {
+                                #t.report_loc(#loc);
+                                #els
+                            }
+                        ),
+                        7,
+                    )));
+                }
+
+                Ok(Some((
+                    quote!(
+                        if #cond {
+                            #t.report_loc(#loc);
+                            #then
+                        }
+                    ),
+                    4,
+                )))
+            }
+
+            "for" => {
+                let vars = match group(tokens.get(2), Delimiter::Parenthesis) {
+                    Some(vars) => syn::parse::Parser::parse2(
+                        Punctuated::<Ident, Token![,]>::parse_terminated,
+                        vars,
+                    )?,
+                    None => return Ok(None),
+                };
+
+                let is_sep = match tokens.get(3) {
+                    Some(TokenTree::Ident(i)) => i == "sep",
+                    _ => false,
+                };
+                let (sep, body, len) = if is_sep {
+                    match (
+                        group(tokens.get(4), Delimiter::Parenthesis),
+                        group(tokens.get(5), Delimiter::Brace),
+                    ) {
+                        (Some(sep), Some(body)) => (sep, body, 6),
+                        _ => return Ok(None),
+                    }
+                } else {
+                    match group(tokens.get(3), Delimiter::Brace) {
+                        Some(body) => (TokenStream::new(), body, 4),
+                        None => return Ok(None),
+                    }
+                };
+
+                let sep = self.quote(sep)?;
+                let body = self.quote(body)?;
+                let vars = vars.into_iter().collect::<Vec<_>>();
+                let first = Ident::new("first", Span::mixed_site());
+                let item = Ident::new("item", Span::mixed_site());
+
+                Ok(Some((
+                    quote!({
+                        #[allow(unused_imports)]
+                        use #krate::spanned_quote::__private::{RepAsIteratorExt as _, RepIteratorExt as _};
+
+                        #(
+                            #[allow(non_snake_case, unused_mut)]
+                            let mut #vars = #vars.__sq_rep();
+                        )*
+
+                        let mut #first = true;
+                        loop {
+                            #(
+                                #[allow(non_snake_case)]
+                                let #vars = match #vars.next() {
+                                    Some(#item) => #item,
+                                    None => break,
+                                };
+                            )*
+
+                            if !#first {
+                                #sep
+                            }
+                            #first = false;
+
+                            #body
+                        }
+                    }),
+                    len,
+                )))
+            }
+
+            _ => Ok(None),
+        }
+    }
+
+    /// Same as `quote`, but ignores variables and directives.
+    fn quote_raw(&self, tokens: TokenStream) -> TokenStream {
+        let t = tokens_ident();
+        let krate = self.krate;
+        let tokens = tokens.into_iter().collect::<Vec<_>>();
+        let mut stmts = TokenStream::new();
+
+        let mut i = 0;
+        while i < tokens.len() {
+            match tokens[i] {
+                TokenTree::Group(ref g) if g.delimiter() != Delimiter::None => {
+                    let delim = match g.delimiter() {
+                        Delimiter::Parenthesis => quote!(Parenthesis),
+                        Delimiter::Brace => quote!(Brace),
+                        _ => quote!(Bracket),
+                    };
+                    let body = self.quote_raw(g.stream());
+                    stmts.extend(quote_spanned!(Span::mixed_site()=>
+                        #t.push_group(#krate::proc_macro2::Delimiter::#delim, |#t: &mut #krate::Quote| {
+                            #body
+                        });
+                    ));
+                    i += 1;
+                }
+                TokenTree::Group(ref g) => {
+                    stmts.extend(self.quote_raw(g.stream()));
+                    i += 1;
+                }
+                ref tt => {
+                    stmts.extend(push_token(krate, tt));
+                    i += 1;
+                }
+            }
+        }
+
+        stmts
+    }
+}
+
+fn group(tt: Option<&TokenTree>, delim: Delimiter) -> Option<TokenStream> {
+    match tt {
+        Some(TokenTree::Group(g)) if g.delimiter() == delim => Some(g.stream()),
+        _ => None,
+    }
+}
+
+/// Returns a statement which quotes `tt`, which is not a group.
+///
+/// Tokens are created directly instead of being parsed at runtime.
+fn push_token(krate: &TokenStream, tt: &TokenTree) -> TokenStream {
+    let t = tokens_ident();
+
+    match tt {
+        TokenTree::Ident(ident) => {
+            let s = ident.to_string();
+            quote!(#t.push_ident(#s);)
+        }
+        TokenTree::Punct(p) => {
+            let ch = p.as_char();
+            let spacing = match p.spacing() {
+                Spacing::Joint => quote!(Joint),
+                Spacing::Alone => quote!(Alone),
+            };
+            quote!(#t.push_punct(#ch, #krate::proc_macro2::Spacing::#spacing);)
+        }
+        TokenTree::Literal(lit) => {
+            let lit = literal(krate, lit);
+            quote!(#t.push_literal(#lit);)
+        }
+        TokenTree::Group(..) => unreachable!("push_token: group"),
+    }
+}
+
+/// Returns an expression which creates `lit`.
+fn literal(krate: &TokenStream, lit: &Literal) -> TokenStream {
+    let s = lit.to_string();
+
+    match Lit::new(lit.clone()) {
+        Lit::Str(ref l) if l.suffix().is_empty() && !s.starts_with('r') => {
+            let value = l.value();
+            return quote!(#krate::proc_macro2::Literal::string(#value));
+        }
+        Lit::Int(ref l) if l.suffix().is_empty() && l.base10_digits() == s => {
+            if let Ok(value) = l.base10_parse::<u64>() {
+                return quote!(#krate::proc_macro2::Literal::u64_unsuffixed(#value));
+            }
+        }
+        _ => {}
+    }
+
+    quote!(#s.parse::<#krate::proc_macro2::Literal>().expect("Failed to parse literal to quote"))
+}
@@ -1,5 +1,4 @@
-//!
-//!
+//! Helpers for generating comments.
 use super::SpanExt;
 use proc_macro2::Span;
 use syn::punctuated::Pair;
@@ -0,0 +1,382 @@
+//! Diagnostics which work on stable.
+//!
+//! On stable, errors are lowered to `compile_error!` invocations, and
+//! messages of notes and help are appended to the message of the error. With
+//! feature `nightly`, diagnostics are emitted using `proc_macro::Diagnostic`.
+//!
+//!```rust,ignore
+//! return Diagnostic::spanned(Level::Error, &input.generics, "generics are not supported")
+//!     .span_note(attr.span(), "required by this attribute")
+//!     .help("remove generics")
+//!     .emit()
+//!     .into();
+//!```
+
+use crate::respan::Respan;
+use proc_macro2::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+use quote::{quote, quote_spanned, ToTokens};
+
+/// Level of a [`Diagnostic`].
+///
+/// On stable, only diagnostics at `Error` are emitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+/// Builder for a diagnostic message.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    level: Level,
+    span: (Span, Span),
+    message: String,
+    children: Vec<SubDiagnostic>,
+}
+
+/// Note or help attached to a [`Diagnostic`].
+#[derive(Debug, Clone)]
+struct SubDiagnostic {
+    level: Level,
+    span: Option<(Span, Span)>,
+    message: String,
+}
+
+impl Diagnostic {
+    pub fn new<T: Into<String>>(level: Level, span: Span, message: T) -> Self {
+        Diagnostic {
+            level,
+            span: (span, span),
+            message: message.into(),
+            children: vec![],
+        }
+    }
+
+    /// Creates a diagnostic pointing at all of `tokens`, from the first token
+    /// to the last token.
+    pub fn spanned<T: Into<String>>(level: Level, tokens: &dyn ToTokens, message: T) -> Self {
+        let first_last = crate::respan::first_last_spans(tokens);
+        Diagnostic {
+            span: first_last,
+            ..Diagnostic::new(level, first_last.0, message)
+        }
+    }
+
+    /// Creates a diagnostic using the first two spans of `span`, like
+    /// tokens generated by [`Quote`](crate::Quote).
+    pub fn respanned<T: Into<String>>(level: Level, span: &dyn Respan, message: T) -> Self {
+        let first = span.next_span();
+        Diagnostic {
+            span: (first, span.next_span()),
+            ..Diagnostic::new(level, first, message)
+        }
+    }
+
+    pub fn error<T: Into<String>>(span: Span, message: T) -> Self {
+        Self::new(Level::Error, span, message)
+    }
+
+    pub fn warning<T: Into<String>>(span: Span, message: T) -> Self {
+        Self::new(Level::Warning, span, message)
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Primary span of `self`.
+    pub fn span(&self) -> Span {
+        self.span.0
+    }
+
+    pub fn note<T: Into<String>>(self, message: T) -> Self {
+        self.child(Level::Note, None, message.into())
+    }
+
+    pub fn span_note<T: Into<String>>(self, span: Span, message: T) -> Self {
+        self.child(Level::Note, Some((span, span)), message.into())
+    }
+
+    pub fn help<T: Into<String>>(self, message: T) -> Self {
+        self.child(Level::Help, None, message.into())
+    }
+
+    pub fn span_help<T: Into<String>>(self, span: Span, message: T) -> Self {
+        self.child(Level::Help, Some((span, span)), message.into())
+    }
+
+    fn child(mut self, level: Level, span: Option<(Span, Span)>, message: String) -> Self {
+        self.children.push(SubDiagnostic {
+            level,
+            span,
+            message,
+        });
+        self
+    }
+
+    /// Emits `self`.
+    ///
+    /// Returned tokens should be appended to the output of the macro. They
+    /// are empty if `self` is emitted using `proc_macro::Diagnostic`.
+    pub fn emit(self) -> TokenStream {
+        #[cfg(feature = "nightly")]
+        {
+            if proc_macro::is_available() {
+                self.into_proc_macro().emit();
+                return TokenStream::new();
+            }
+        }
+
+        self.lower()
+    }
+
+    /// Lowers `self` to `compile_error!` invocations, or a use of a deprecated
+    /// item for warnings. See [`warning`] for details.
+    ///
+    /// Notes and help are appended to the message. For errors, the ones
+    /// attached to a span are also emitted at the span, as an error.
+    /// Diagnostics at `Note` and `Help` are ignored.
+    pub fn lower(&self) -> TokenStream {
+        let mut message = self.message.clone();
+        for (i, child) in self.children.iter().enumerate() {
+            message.push_str(if i == 0 { "\n\n" } else { "\n" });
+            message.push_str(&child.to_string());
+        }
+
+        let mut tokens = TokenStream::new();
+        match self.level {
+            Level::Error => tokens.extend(compile_error(self.span, &message)),
+            Level::Warning => return deprecated(self.span.0, &message),
+            Level::Note | Level::Help => return tokens,
+        }
+
+        for child in &self.children {
+            if let Some(span) = child.span {
+                tokens.extend(compile_error(span, &child.to_string()));
+            }
+        }
+
+        tokens
+    }
+
+    /// Converts `self` into `proc_macro::Diagnostic`.
+    ///
+    /// This panics if it's invoked outside of proc-macro.
+    #[cfg(feature = "nightly")]
+    pub fn into_proc_macro(self) -> proc_macro::Diagnostic {
+        let mut diag =
+            proc_macro::Diagnostic::spanned(join(self.span), self.level.into(), self.message);
+        for child in self.children {
+            diag = match (child.level, child.span) {
+                (Level::Note, Some(span)) => diag.span_note(join(span), child.message),
+                (Level::Note, None) => diag.note(child.message),
+                (Level::Help, Some(span)) => diag.span_help(join(span), child.message),
+                (Level::Help, None) => diag.help(child.message),
+                (Level::Warning, Some(span)) => diag.span_warning(join(span), child.message),
+                (Level::Warning, None) => diag.warning(child.message),
+                (Level::Error, Some(span)) => diag.span_error(join(span), child.message),
+                (Level::Error, None) => diag.error(child.message),
+            };
+        }
+        diag
+    }
+}
+
+impl std::fmt::Display for SubDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let level = match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+            Level::Help => "help",
+        };
+        write!(f, "{}: {}", level, self.message)
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl From<Level> for proc_macro::Level {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => proc_macro::Level::Error,
+            Level::Warning => proc_macro::Level::Warning,
+            Level::Note => proc_macro::Level::Note,
+            Level::Help => proc_macro::Level::Help,
+        }
+    }
+}
+
+#[cfg(feature = "nightly")]
+fn join((first, last): (Span, Span)) -> proc_macro::Span {
+    first.join(last).unwrap_or(first).unwrap()
+}
+
+/// Emits a warning at `span`.
+///
+/// On stable, this emits a use of a `#[deprecated]` function, so the message
+/// is shown like `use of deprecated function `warning`: message`. Returned
+/// tokens are an item, and should be appended to the output of the macro. The
+/// warning can be suppressed by `#[allow(deprecated)]`.
+///
+/// With feature `nightly`, this uses `proc_macro::Diagnostic` instead.
+///
+///```rust,ignore
+/// if attr.path().is_ident("old_key") {
+///     tokens.extend(diag::warning(attr.span(), "`old_key` is deprecated, use `new_key`"));
+/// }
+///```
+pub fn warning<T: Into<String>>(span: Span, message: T) -> TokenStream {
+    Diagnostic::warning(span, message).emit()
+}
+
+/// `const _: () = { #[deprecated(note = message)] const fn warning() {} warning(); };`
+///
+/// Only the call is located at `span`. Both names resolve at call site, so
+/// they refer to the same function.
+fn deprecated(span: Span, message: &str) -> TokenStream {
+    let call = quote_spanned!(Span::call_site().located_at(span)=> warning(););
+
+    quote!(
+        const _: () = {
+            #[deprecated(note = #message)]
+            const fn warning() {}
+            #call
+        };
+    )
+}
+
+/// `::core::compile_error! { message }`, spanned like `syn::Error`.
+fn compile_error((first, last): (Span, Span), message: &str) -> TokenStream {
+    let punct = |ch, spacing, span| {
+        let mut p = Punct::new(ch, spacing);
+        p.set_span(span);
+        TokenTree::Punct(p)
+    };
+
+    let mut lit = Literal::string(message);
+    lit.set_span(last);
+    let mut group = Group::new(Delimiter::Brace, TokenTree::Literal(lit).into());
+    group.set_span(last);
+
+    vec![
+        punct(':', Spacing::Joint, first),
+        punct(':', Spacing::Alone, first),
+        TokenTree::Ident(Ident::new("core", first)),
+        punct(':', Spacing::Joint, first),
+        punct(':', Spacing::Alone, first),
+        TokenTree::Ident(Ident::new("compile_error", first)),
+        punct('!', Spacing::Alone, first),
+        TokenTree::Group(group),
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// Collects errors, so they can be reported at once.
+///
+///```rust,ignore
+/// let mut errors = Errors::new();
+/// for field in &fields {
+///     if let Some(ty) = errors.ok_or_push(parse_attr(field)) {
+///         // ..
+///     }
+/// }
+/// let warnings = errors.finish()?;
+///```
+#[derive(Debug, Default)]
+pub struct Errors {
+    errors: Vec<Entry>,
+}
+
+#[derive(Debug)]
+enum Entry {
+    Syn(syn::Error),
+    Diagnostic(Diagnostic),
+}
+
+impl Errors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `err`, which can be a `syn::Error` or a [`Diagnostic`].
+    pub fn push<E>(&mut self, err: E)
+    where
+        Self: Extend<E>,
+    {
+        self.extend(Some(err));
+    }
+
+    /// Returns the value of `res`, or adds its error and returns `None`.
+    pub fn ok_or_push<T, E>(&mut self, res: Result<T, E>) -> Option<T>
+    where
+        Self: Extend<E>,
+    {
+        match res {
+            Ok(v) => Some(v),
+            Err(err) => {
+                self.push(err);
+                None
+            }
+        }
+    }
+
+    /// Returns true if nothing is added, including warnings.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Returns true if any error is added. Diagnostics which are not errors
+    /// are not counted.
+    pub fn has_errors(&self) -> bool {
+        self.errors.iter().any(|e| match e {
+            Entry::Syn(..) => true,
+            Entry::Diagnostic(d) => d.level() == Level::Error,
+        })
+    }
+
+    /// Returns `Err` with all diagnostics if any error is added, so the
+    /// caller can bail out.
+    ///
+    /// Otherwise, returns tokens of warnings, which should be appended to the
+    /// output of the macro. On stable, warnings are emitted as items.
+    pub fn finish(self) -> Result<TokenStream, TokenStream> {
+        if self.has_errors() {
+            Err(self.into())
+        } else {
+            Ok(self.into())
+        }
+    }
+}
+
+impl Extend<syn::Error> for Errors {
+    fn extend<I: IntoIterator<Item = syn::Error>>(&mut self, iter: I) {
+        self.errors.extend(iter.into_iter().map(Entry::Syn))
+    }
+}
+
+impl Extend<Diagnostic> for Errors {
+    fn extend<I: IntoIterator<Item = Diagnostic>>(&mut self, iter: I) {
+        self.errors.extend(iter.into_iter().map(Entry::Diagnostic))
+    }
+}
+
+/// Emits all diagnostics.
+impl From<Errors> for TokenStream {
+    fn from(errors: Errors) -> Self {
+        errors
+            .errors
+            .into_iter()
+            .map(|e| match e {
+                Entry::Syn(err) => err.to_compile_error(),
+                Entry::Diagnostic(diag) => diag.emit(),
+            })
+            .collect()
+    }
+}
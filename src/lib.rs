@@ -1,24 +1,38 @@
 //! Utils for implementing proc-macro. Works on stable.
 //!
-//!
-//!
+//! `Quote`, `smart_quote!` and `q!` also work outside of proc-macro, like
+//! build scripts or code generators. Disable default feature `proc-macro` to
+//! remove dependency on `proc_macro`.
 
 #![recursion_limit = "128"]
+#![cfg_attr(
+    feature = "nightly",
+    feature(proc_macro_def_site, proc_macro_diagnostic)
+)]
 
+#[cfg(feature = "proc-macro")]
 extern crate proc_macro;
 pub use proc_macro2;
 pub use quote;
 pub use syn;
 
 pub use self::span_ext::SpanExt;
+#[cfg(feature = "span-locations")]
+pub use self::span_ext::SpanLocation;
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub use pmutil_macros::smart_quote as __smart_quote;
 use proc_macro2::TokenStream;
 use quote::ToTokens;
 pub use spanned_quote::Quote;
 use syn::Ident;
 
 pub mod comment;
+pub mod diag;
 pub mod prelude;
+mod pretty;
 pub mod respan;
+mod span_dump;
 mod span_ext;
 pub mod spanned_quote;
 pub mod synom_ext;
@@ -53,10 +67,37 @@ pub trait ToTokensExt: ToTokens {
         tmp
     }
 
+    /// Formats tokens into indented rust source.
+    ///
+    /// This works for fragments which are not valid items or expressions.
+    fn to_pretty_string(&self) -> String {
+        pretty::print(&self.dump(), None)
+    }
+
+    /// Prints each token in a line, with the location of its span, or
+    /// `<call_site>`/`<mixed_site>`.
+    ///
+    /// With feature `span-locations`, the line which the span points at is
+    /// printed with carets under it. It's taken from `source` if given, and
+    /// from the source file otherwise. Note that spans cannot be distinguished
+    /// outside of proc-macro without `span-locations`.
+    fn dump_spans(&self, source: Option<&str>) -> String {
+        span_dump::print(&self.dump(), source, None)
+    }
+
     /// Usage: `Quote::new(body.first_last())`
     fn first_last(&self) -> respan::FirstLast {
         respan::FirstLast::from_tokens(&self)
     }
+
+    /// Spans from the first token to the last token of `self`. See
+    /// [`SpanExt::join_range`].
+    ///
+    /// Usage: `Quote::new(expr.span_range())`
+    fn span_range(&self) -> respan::FirstLast {
+        let (first, last) = respan::first_last_spans(&self);
+        first.join_range(last)
+    }
 }
 
 impl<T: ToTokens> ToTokensExt for T {}
@@ -0,0 +1,339 @@
+//! Pretty printer for token streams.
+//!
+//! This works on tokens instead of syntax trees, so it can print fragments
+//! which are not valid items or expressions. Output is not as good as
+//! rustfmt, but it's readable.
+
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+
+/// Keywords which are followed by a space even if next token is a group.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "break", "const", "dyn", "else", "for", "if", "impl", "in", "let", "loop",
+    "match", "move", "mut", "ref", "return", "static", "type", "unsafe", "use", "where", "while",
+    "yield",
+];
+
+/// Keywords which are followed by a name and its generic parameters.
+const DECLS: &[&str] = &["enum", "fn", "struct", "trait", "type", "union"];
+
+/// Pretty prints `tts`.
+///
+/// If `mark` is `Some`, the token with the index is marked with carets. Tokens
+/// are indexed in depth-first order, and a group is counted before its
+/// contents.
+pub(crate) fn print(tts: &TokenStream, mark: Option<usize>) -> String {
+    let mut p = Printer {
+        buf: String::new(),
+        indent: 0,
+        col: 0,
+        index: 0,
+        mark,
+        caret: None,
+        prev: Prev::LineStart,
+        generics: 0,
+        decl: false,
+        pending_newline: false,
+        attr: false,
+    };
+    p.stream(tts.clone(), true);
+    p.newline();
+
+    let len = p.buf.trim_end().len();
+    p.buf.truncate(len);
+    p.buf
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prev {
+    LineStart,
+    Open,
+    Close,
+    /// Identifier or literal.
+    Word {
+        /// Whether `<` after this starts generic arguments, instead of being
+        /// a comparison.
+        generics: bool,
+    },
+    Keyword,
+    Punct {
+        ch: char,
+        joint: bool,
+        /// No space after this, like unary operators or `::`.
+        tight: bool,
+    },
+}
+
+struct Printer {
+    buf: String,
+    indent: usize,
+    /// Column in the current line.
+    col: usize,
+    /// Index of the next token.
+    index: usize,
+    mark: Option<usize>,
+    /// Column and width of the marked token in the current line.
+    caret: Option<(usize, usize)>,
+    prev: Prev,
+    /// Depth of generic arguments.
+    generics: usize,
+    /// Set after a keyword in `DECLS`.
+    decl: bool,
+    /// Set after a block. Cancelled by tokens like `;` or `else`.
+    pending_newline: bool,
+    /// Set while printing an attribute which starts a line.
+    attr: bool,
+}
+
+impl Printer {
+    fn stream(&mut self, tts: TokenStream, block: bool) {
+        for tt in tts {
+            match tt {
+                TokenTree::Group(g) => {
+                    let index = self.next_index();
+                    match g.delimiter() {
+                        Delimiter::Brace => self.block(g.stream(), block, index),
+                        Delimiter::Parenthesis => self.group('(', ')', g.stream(), index),
+                        Delimiter::Bracket => {
+                            let attr = self.attr;
+                            self.group('[', ']', g.stream(), index);
+                            if attr && block {
+                                self.attr = false;
+                                self.newline();
+                            }
+                        }
+                        Delimiter::None => self.stream(g.stream(), block),
+                    }
+                }
+                TokenTree::Ident(i) => {
+                    let index = self.next_index();
+                    let s = i.to_string();
+                    if s == "else" {
+                        self.pending_newline = false;
+                    }
+                    self.flush_newline();
+                    if self.word_needs_space() {
+                        self.write(" ", None);
+                    }
+                    self.write(&s, Some(index));
+                    // `Vec<`, `fn new<` or `iter::<`, but not `x < y`.
+                    let generics = self.decl
+                        || s.starts_with(|c: char| c.is_ascii_uppercase())
+                        || matches!(
+                            self.prev,
+                            Prev::Punct {
+                                ch: ':',
+                                tight: true,
+                                ..
+                            }
+                        );
+                    self.decl = DECLS.contains(&&*s);
+                    self.prev = if KEYWORDS.contains(&&*s) {
+                        Prev::Keyword
+                    } else {
+                        Prev::Word { generics }
+                    };
+                }
+                TokenTree::Literal(l) => {
+                    let index = self.next_index();
+                    self.flush_newline();
+                    if self.word_needs_space() {
+                        self.write(" ", None);
+                    }
+                    self.write(&l.to_string(), Some(index));
+                    self.decl = false;
+                    self.prev = Prev::Word { generics: false };
+                }
+                TokenTree::Punct(p) => {
+                    let index = self.next_index();
+                    self.punct(p.as_char(), p.spacing() == Spacing::Joint, block, index)
+                }
+            }
+        }
+    }
+
+    fn next_index(&mut self) -> usize {
+        let index = self.index;
+        self.index += 1;
+        index
+    }
+
+    fn block(&mut self, tts: TokenStream, in_block: bool, index: usize) {
+        self.flush_newline();
+        if !matches!(self.prev, Prev::LineStart | Prev::Open) {
+            self.write(" ", None);
+        }
+
+        if tts.is_empty() {
+            self.write("{", Some(index));
+            self.write("}", None);
+        } else {
+            self.write("{", Some(index));
+            self.prev = Prev::Open;
+            self.indent += 1;
+            self.newline();
+            self.stream(tts, true);
+            self.pending_newline = false;
+            self.indent -= 1;
+            self.newline();
+            self.write("}", None);
+        }
+        self.prev = Prev::Close;
+        self.generics = 0;
+        self.pending_newline = in_block;
+    }
+
+    fn group(&mut self, open: char, close: char, tts: TokenStream, index: usize) {
+        self.flush_newline();
+        let space = match self.prev {
+            Prev::LineStart | Prev::Open | Prev::Word { .. } | Prev::Close => false,
+            Prev::Keyword => true,
+            Prev::Punct { ch, tight, .. } => !tight && !matches!(ch, '#' | '!'),
+        };
+        if space {
+            self.write(" ", None);
+        }
+
+        let generics = self.generics;
+        self.write(&open.to_string(), Some(index));
+        self.prev = Prev::Open;
+        self.generics = 0;
+        self.stream(tts, false);
+        self.pending_newline = false;
+        self.write(&close.to_string(), None);
+        self.prev = Prev::Close;
+        self.generics = generics;
+    }
+
+    fn punct(&mut self, ch: char, joint: bool, block: bool, index: usize) {
+        if matches!(ch, ';' | ',' | '.' | '?') {
+            self.pending_newline = false;
+        }
+        self.flush_newline();
+
+        let (prev_ch, prev_joint) = match self.prev {
+            Prev::Punct { ch, joint, .. } => (Some(ch), joint),
+            _ => (None, false),
+        };
+        let operand = matches!(self.prev, Prev::Word { .. } | Prev::Close);
+        let start = matches!(self.prev, Prev::LineStart);
+        let tight_before = matches!(
+            self.prev,
+            Prev::LineStart | Prev::Open | Prev::Punct { tight: true, .. }
+        );
+
+        let mut tight = false;
+        let mut closes_generics = false;
+        let space = if prev_joint {
+            // `::`, `->`, `'a`, ...
+            match (prev_ch, ch) {
+                (Some(':'), ':') => tight = true,
+                (Some(':'), '<') => {
+                    self.generics += 1;
+                    tight = true;
+                }
+                (Some('>'), '>') if self.generics > 0 => {
+                    self.generics -= 1;
+                    closes_generics = true;
+                }
+                _ => {}
+            }
+            false
+        } else {
+            match ch {
+                ',' | ';' | '.' | '?' => false,
+                ':' => joint && !operand && !tight_before,
+                '!' if operand => false,
+                '<' if matches!(
+                    self.prev,
+                    Prev::Word { generics: true } | Prev::Keyword | Prev::Punct { ch: ':', .. }
+                ) =>
+                {
+                    self.generics += 1;
+                    tight = true;
+                    false
+                }
+                '>' if self.generics > 0 => {
+                    self.generics -= 1;
+                    closes_generics = true;
+                    false
+                }
+                '&' | '*' | '-' | '!' if !operand => {
+                    tight = true;
+                    // `<-` is not a valid token.
+                    !tight_before || (ch == '-' && prev_ch == Some('<'))
+                }
+                _ => !tight_before,
+            }
+        };
+        if space {
+            self.write(" ", None);
+        }
+
+        self.write(&ch.to_string(), Some(index));
+        self.prev = if closes_generics && !joint {
+            Prev::Close
+        } else {
+            Prev::Punct { ch, joint, tight }
+        };
+
+        match ch {
+            '#' if start => self.attr = true,
+            '!' if self.attr => {}
+            _ => self.attr = false,
+        }
+
+        if block && !joint && self.generics == 0 && matches!(ch, ';' | ',') {
+            self.newline();
+        }
+    }
+
+    fn word_needs_space(&self) -> bool {
+        match self.prev {
+            Prev::LineStart | Prev::Open => false,
+            Prev::Word { .. } | Prev::Keyword | Prev::Close => true,
+            Prev::Punct { ch, joint, tight } => !joint && !tight && !matches!(ch, '.' | '#'),
+        }
+    }
+
+    fn flush_newline(&mut self) {
+        if self.pending_newline {
+            self.pending_newline = false;
+            self.newline();
+        }
+    }
+
+    fn newline(&mut self) {
+        if self.prev == Prev::LineStart {
+            return;
+        }
+
+        let len = self.buf.trim_end_matches(' ').len();
+        self.buf.truncate(len);
+        self.buf.push('\n');
+
+        if let Some((col, width)) = self.caret.take() {
+            self.buf.push_str(&" ".repeat(col));
+            self.buf.push_str(&"^".repeat(width));
+            self.buf.push('\n');
+        }
+
+        self.col = 0;
+        self.prev = Prev::LineStart;
+        self.generics = 0;
+    }
+
+    fn write(&mut self, s: &str, index: Option<usize>) {
+        if self.col == 0 && self.prev == Prev::LineStart && !s.trim().is_empty() {
+            let indent = "    ".repeat(self.indent);
+            self.buf.push_str(&indent);
+            self.col = indent.len();
+        }
+
+        if index.is_some() && index == self.mark {
+            self.caret = Some((self.col, s.chars().count()));
+        }
+
+        self.buf.push_str(s);
+        self.col += s.chars().count();
+    }
+}
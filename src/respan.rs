@@ -1,8 +1,11 @@
 //! Span support for quasi-quotting.
 
-use proc_macro2::{Span, TokenStream, TokenTree};
+use crate::SpanExt;
+use proc_macro2::{Group, Span, TokenStream, TokenTree};
 use quote::ToTokens;
 use std::cell::Cell;
+use std::collections::HashMap;
+use syn::parse::Parse;
 
 pub trait Respan {
     /// Used while quasi quotting.
@@ -28,13 +31,17 @@ impl Respan for Span {
     }
 }
 
-impl<'a, S> Respan for &'a S
+impl<S> Respan for &S
 where
     S: ?Sized + Respan,
 {
     fn next_span(&self) -> Span {
         <S as Respan>::next_span(self)
     }
+
+    fn respan(&self, tt: TokenTree) -> TokenTree {
+        <S as Respan>::respan(self, tt)
+    }
 }
 
 impl<S> Respan for Box<S>
@@ -44,6 +51,33 @@ where
     fn next_span(&self) -> Span {
         <S as Respan>::next_span(self)
     }
+
+    fn respan(&self, tt: TokenTree) -> TokenTree {
+        <S as Respan>::respan(self, tt)
+    }
+}
+
+/// Calls `f` for each token.
+///
+/// Usage: `Quote::new(respan::from_fn(move || Span::mixed_site().located_at(span)))`
+pub fn from_fn<F>(f: F) -> FromFn<F>
+where
+    F: Fn() -> Span,
+{
+    FromFn(f)
+}
+
+/// Created by [`from_fn`].
+#[derive(Debug, Clone, Copy)]
+pub struct FromFn<F>(F);
+
+impl<F> Respan for FromFn<F>
+where
+    F: Fn() -> Span,
+{
+    fn next_span(&self) -> Span {
+        (self.0)()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -59,18 +93,283 @@ impl Respan for FirstLast {
 }
 
 impl FirstLast {
-    pub fn from_tokens(tokens: &dyn ToTokens) -> Self {
-        let mut spans = TokenStream::new();
-        tokens.to_tokens(&mut spans);
-        let good_tokens = spans.into_iter().collect::<Vec<_>>();
-        let first_span = good_tokens
-            .first()
-            .map(|t| t.span())
-            .unwrap_or(Span::call_site());
-        let last = good_tokens.last().map(|t| t.span()).unwrap_or(first_span);
+    /// Uses `first` for the first token, and `last` for the others.
+    pub fn new(first: Span, last: Span) -> Self {
         FirstLast {
-            first: Cell::new(Some(first_span)),
+            first: Cell::new(Some(first)),
             last,
         }
     }
+
+    pub fn from_tokens(tokens: &dyn ToTokens) -> Self {
+        let (first, last) = first_last_spans(tokens);
+        Self::new(first, last)
+    }
+}
+
+/// Spans of the first and last token of `tokens`.
+pub(crate) fn first_last_spans(tokens: &dyn ToTokens) -> (Span, Span) {
+    let mut spans = TokenStream::new();
+    tokens.to_tokens(&mut spans);
+    let good_tokens = spans.into_iter().collect::<Vec<_>>();
+    let first_span = good_tokens
+        .first()
+        .map(|t| t.span())
+        .unwrap_or(Span::call_site());
+    let last = good_tokens.last().map(|t| t.span()).unwrap_or(first_span);
+    (first_span, last)
+}
+
+/// Uses spans of tokens in order, including delimiters of groups. Once all
+/// spans are used, the last one is used for remaining tokens.
+///
+/// Usage: `Quote::new(Sequential::from_tokens(&input))`
+#[derive(Debug, Clone)]
+pub struct Sequential {
+    spans: Vec<Span>,
+    next: Cell<usize>,
+}
+
+impl Respan for Sequential {
+    fn next_span(&self) -> Span {
+        let next = self.next.get();
+        match self.spans.get(next) {
+            Some(span) => {
+                self.next.set(next + 1);
+                *span
+            }
+            None => self.spans.last().copied().unwrap_or_else(Span::call_site),
+        }
+    }
+}
+
+impl Sequential {
+    pub fn new(spans: Vec<Span>) -> Self {
+        Sequential {
+            spans,
+            next: Cell::new(0),
+        }
+    }
+
+    pub fn from_tokens(tokens: &dyn ToTokens) -> Self {
+        Self::new(spans_of(tokens.to_token_stream()))
+    }
+}
+
+/// Same as [`Sequential`], but starts over once all spans are used.
+#[derive(Debug, Clone)]
+pub struct Cycle {
+    spans: Vec<Span>,
+    next: Cell<usize>,
+}
+
+impl Respan for Cycle {
+    fn next_span(&self) -> Span {
+        if self.spans.is_empty() {
+            return Span::call_site();
+        }
+
+        let next = self.next.get();
+        self.next.set((next + 1) % self.spans.len());
+        self.spans[next]
+    }
+}
+
+impl Cycle {
+    pub fn new(spans: Vec<Span>) -> Self {
+        Cycle {
+            spans,
+            next: Cell::new(0),
+        }
+    }
+
+    pub fn from_tokens(tokens: &dyn ToTokens) -> Self {
+        Self::new(spans_of(tokens.to_token_stream()))
+    }
+}
+
+/// Spans of all tokens in `tokens`, in depth-first order.
+fn spans_of(tokens: TokenStream) -> Vec<Span> {
+    let mut spans = vec![];
+    for tt in tokens {
+        spans.push(tt.span());
+        if let TokenTree::Group(g) = tt {
+            spans.extend(spans_of(g.stream()));
+        }
+    }
+    spans
+}
+
+/// Gives a generated identifier the span of the identifier with the same
+/// name in input, and uses `S` for other tokens.
+///
+/// Usage: `Quote::new(ByIdent::from_tokens(&input, input.first_last()))`
+#[derive(Debug, Clone)]
+pub struct ByIdent<S> {
+    idents: HashMap<String, Span>,
+    fallback: S,
+}
+
+impl<S> Respan for ByIdent<S>
+where
+    S: Respan,
+{
+    fn next_span(&self) -> Span {
+        self.fallback.next_span()
+    }
+
+    fn respan(&self, tt: TokenTree) -> TokenTree {
+        match tt {
+            TokenTree::Ident(mut ident) => match self.idents.get(&ident.to_string()) {
+                Some(span) => {
+                    ident.set_span(*span);
+                    TokenTree::Ident(ident)
+                }
+                None => self.fallback.respan(TokenTree::Ident(ident)),
+            },
+            tt => self.fallback.respan(tt),
+        }
+    }
+}
+
+impl<S> ByIdent<S> {
+    /// If an identifier appears multiple times in `tokens`, the first one is
+    /// used.
+    pub fn from_tokens(tokens: &dyn ToTokens, fallback: S) -> Self {
+        fn collect(tokens: TokenStream, idents: &mut HashMap<String, Span>) {
+            for tt in tokens {
+                match tt {
+                    TokenTree::Ident(i) => {
+                        idents.entry(i.to_string()).or_insert_with(|| i.span());
+                    }
+                    TokenTree::Group(g) => collect(g.stream(), idents),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut idents = HashMap::new();
+        collect(tokens.to_token_stream(), &mut idents);
+        ByIdent { idents, fallback }
+    }
+}
+
+/// Resolves names at [`Span::mixed_site`], while keeping location of spans
+/// from `S`.
+///
+/// Usage: `Quote::new(MixedSite(tokens.first_last()))`
+#[derive(Debug, Clone)]
+pub struct MixedSite<S>(pub S);
+
+impl<S> Respan for MixedSite<S>
+where
+    S: Respan,
+{
+    fn next_span(&self) -> Span {
+        Span::mixed_site().located_at(self.0.next_span())
+    }
+
+    fn respan(&self, tt: TokenTree) -> TokenTree {
+        resolve_at(self.0.respan(tt), Span::mixed_site())
+    }
+}
+
+/// Resolves names at `Span::def_site`, while keeping location of spans from
+/// `S`.
+///
+/// Requires nightly compiler and can be used only inside procedural macros.
+#[cfg(feature = "nightly")]
+#[derive(Debug, Clone)]
+pub struct DefSite<S>(pub S);
+
+#[cfg(feature = "nightly")]
+impl<S> Respan for DefSite<S>
+where
+    S: Respan,
+{
+    fn next_span(&self) -> Span {
+        def_site().located_at(self.0.next_span())
+    }
+
+    fn respan(&self, tt: TokenTree) -> TokenTree {
+        resolve_at(self.0.respan(tt), def_site())
+    }
+}
+
+#[cfg(feature = "nightly")]
+pub(crate) fn def_site() -> Span {
+    proc_macro::Span::def_site().into()
+}
+
+/// Respans all tokens in `tokens` using `strategy`, including tokens inside
+/// of groups.
+pub fn respan_tokens(tokens: TokenStream, strategy: &dyn Respan) -> TokenStream {
+    respan_if(tokens, strategy, &|_| true)
+}
+
+/// Same as [`respan_tokens`], but respans only tokens at [`Span::call_site`],
+/// so spans from input are preserved. Other tokens do not consume spans of
+/// `strategy`.
+///
+/// See [`SpanExt::is_call_site`] for limitations.
+///
+/// [`SpanExt::is_call_site`]: crate::SpanExt::is_call_site
+pub fn respan_call_site(tokens: TokenStream, strategy: &dyn Respan) -> TokenStream {
+    respan_if(tokens, strategy, &|span| span.is_call_site())
+}
+
+/// Respans all tokens in `node` using `strategy`.
+///
+/// `node` is printed and parsed again, so `T` should implement `Parse`. On
+/// error, `node` is not changed. Some nodes like `syn::Field` and
+/// `syn::Variant` do not implement `Parse`. For them, respan tokens with
+/// [`respan_tokens`] and parse them with a parser like
+/// `syn::Field::parse_named`.
+pub fn respan_node<T>(node: &mut T, strategy: &dyn Respan) -> syn::Result<()>
+where
+    T: Parse + ToTokens,
+{
+    *node = syn::parse2(respan_tokens(node.to_token_stream(), strategy))?;
+    Ok(())
+}
+
+/// Same as [`respan_node`], but respans only tokens at [`Span::call_site`].
+pub fn respan_node_call_site<T>(node: &mut T, strategy: &dyn Respan) -> syn::Result<()>
+where
+    T: Parse + ToTokens,
+{
+    *node = syn::parse2(respan_call_site(node.to_token_stream(), strategy))?;
+    Ok(())
+}
+
+fn respan_if(
+    tokens: TokenStream,
+    strategy: &dyn Respan,
+    filter: &dyn Fn(Span) -> bool,
+) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|tt| match tt {
+            TokenTree::Group(g) => {
+                // Delimiters are respanned before contents, like `Quote::push_group`.
+                let span = if filter(g.span()) {
+                    let empty = Group::new(g.delimiter(), TokenStream::new());
+                    strategy.respan(TokenTree::Group(empty)).span()
+                } else {
+                    g.span()
+                };
+                let mut group = Group::new(g.delimiter(), respan_if(g.stream(), strategy, filter));
+                group.set_span(span);
+                TokenTree::Group(group)
+            }
+            tt if filter(tt.span()) => strategy.respan(tt),
+            tt => tt,
+        })
+        .collect()
+}
+
+fn resolve_at(mut tt: TokenTree, at: Span) -> TokenTree {
+    let span = tt.span().resolved_at(at);
+    tt.set_span(span);
+    tt
 }
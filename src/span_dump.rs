@@ -0,0 +1,135 @@
+//! Prints each token with the location of its span, for debugging respanning.
+
+use crate::SpanExt;
+use proc_macro2::{Delimiter, Span, TokenStream, TokenTree};
+use std::fmt::Write;
+
+/// Prints each token of `tts` in a line, followed by the location of its span.
+///
+/// With feature `span-locations`, the line of `source` (or the source file,
+/// if it's known) which the span points at is printed with carets under it.
+/// If `mark` is `Some`, the token with the index is marked like
+/// `pretty::print`.
+pub(crate) fn print(tts: &TokenStream, source: Option<&str>, mark: Option<usize>) -> String {
+    let mut tokens = vec![];
+    flatten(tts, 0, &mut tokens);
+
+    let width = tokens
+        .iter()
+        .map(|(depth, text, _)| depth * 2 + text.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut buf = String::new();
+    for (index, (depth, text, span)) in tokens.into_iter().enumerate() {
+        let label = format!("{}{}", "  ".repeat(depth), text);
+        let marker = if Some(index) == mark {
+            "  <-- here"
+        } else {
+            ""
+        };
+        writeln!(
+            buf,
+            "{:<width$}  {}{}",
+            label,
+            describe(span),
+            marker,
+            width = width
+        )
+        .unwrap();
+
+        if site(span).is_some() {
+            continue;
+        }
+        if let Some((line_no, line, col, len)) = source_line(span, source) {
+            writeln!(buf, "{:>5} | {}", line_no, line).unwrap();
+            writeln!(buf, "      | {}{}", " ".repeat(col), "^".repeat(len.max(1))).unwrap();
+        }
+    }
+
+    let len = buf.trim_end().len();
+    buf.truncate(len);
+    buf
+}
+
+/// Collects tokens in depth-first order. A group is represented by its open
+/// delimiter.
+fn flatten(tts: &TokenStream, depth: usize, buf: &mut Vec<(usize, String, Span)>) {
+    for tt in tts.clone() {
+        match tt {
+            TokenTree::Group(g) => {
+                let open = match g.delimiter() {
+                    Delimiter::Parenthesis => "(",
+                    Delimiter::Brace => "{",
+                    Delimiter::Bracket => "[",
+                    Delimiter::None => "<none>",
+                };
+                buf.push((depth, open.to_string(), g.span()));
+                flatten(&g.stream(), depth + 1, buf);
+            }
+            tt => buf.push((depth, tt.to_string(), tt.span())),
+        }
+    }
+}
+
+/// Returns `<call_site>` or `<mixed_site>` if `span` is one of them.
+fn site(span: Span) -> Option<&'static str> {
+    if span.is_call_site() {
+        Some("<call_site>")
+    } else if span.is_mixed_site() {
+        Some("<mixed_site>")
+    } else {
+        None
+    }
+}
+
+fn describe(span: Span) -> String {
+    if let Some(site) = site(span) {
+        return site.into();
+    }
+
+    #[cfg(feature = "span-locations")]
+    {
+        let loc = span.location();
+        if loc.start.line != 0 {
+            return loc.to_string();
+        }
+    }
+
+    match span.source_text() {
+        Some(text) => format!("`{}`", text),
+        None => "<unknown>".into(),
+    }
+}
+
+/// Returns line number, text of the line, column and width of `span`.
+#[cfg(feature = "span-locations")]
+fn source_line(span: Span, source: Option<&str>) -> Option<(usize, String, usize, usize)> {
+    let loc = span.location();
+    if loc.start.line == 0 {
+        return None;
+    }
+
+    let file;
+    let source = match source {
+        Some(source) => source,
+        None => {
+            file = std::fs::read_to_string(span.local_file()?).ok()?;
+            &file
+        }
+    };
+
+    let line = source.lines().nth(loc.start.line - 1)?;
+    let len = if loc.end.line == loc.start.line {
+        loc.end.column.saturating_sub(loc.start.column)
+    } else {
+        line.chars().count().saturating_sub(loc.start.column)
+    };
+
+    Some((loc.start.line, line.to_string(), loc.start.column, len))
+}
+
+#[cfg(not(feature = "span-locations"))]
+fn source_line(_: Span, _: Option<&str>) -> Option<(usize, String, usize, usize)> {
+    None
+}
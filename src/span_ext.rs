@@ -1,5 +1,10 @@
+use crate::respan::FirstLast;
 use crate::synom_ext::FromSpan;
+#[cfg(feature = "span-locations")]
+use proc_macro2::LineColumn;
 use proc_macro2::Span;
+#[cfg(feature = "span-locations")]
+use std::fmt::{self, Display, Formatter};
 
 /// Extension trait for [Span][] and [syn::Span][].
 ///
@@ -22,9 +27,90 @@ pub trait SpanExt: Copy {
         Token::from_span(self.into_pm2_span())
     }
 
+    /// Spans from `self` to `last`.
+    ///
+    /// With feature `nightly`, this joins them into one span using
+    /// [`Span::join`]. Otherwise, or if they cannot be joined, `self` is used
+    /// for the first token and `last` for the others, which is enough to make
+    /// rustc underline the whole range of an error.
+    fn join_range(self, last: Span) -> FirstLast {
+        let first = self.into_pm2_span();
+
+        #[cfg(feature = "nightly")]
+        {
+            if let Some(joined) = first.join(last) {
+                return FirstLast::new(joined, joined);
+            }
+        }
+
+        FirstLast::new(first, last)
+    }
+
+    /// Returns true if `self` is [`Span::call_site`].
+    ///
+    /// Spans cannot be compared on stable, so this compares their debug
+    /// representation. Outside of proc-macro, all spans are considered as
+    /// `call_site` unless feature `span-locations` of `proc-macro2` is
+    /// enabled.
+    fn is_call_site(self) -> bool {
+        format!("{:?}", self.into_pm2_span()) == format!("{:?}", Span::call_site())
+    }
+
+    /// Returns true if `self` is [`Span::mixed_site`].
+    ///
+    /// This has the same limitations as [`SpanExt::is_call_site`].
+    fn is_mixed_site(self) -> bool {
+        format!("{:?}", self.into_pm2_span()) == format!("{:?}", Span::mixed_site())
+    }
+
+    /// Start and end of `self`.
+    ///
+    /// Locations are available only outside of proc-macro or on nightly
+    /// compiler. Lines are 1-indexed, and columns are 0-indexed.
+    #[cfg(feature = "span-locations")]
+    fn location(self) -> SpanLocation {
+        let span = self.into_pm2_span();
+        SpanLocation {
+            start: span.start(),
+            end: span.end(),
+        }
+    }
+
+    /// Panics if `self` does not start at `line`.
+    #[cfg(feature = "span-locations")]
+    #[track_caller]
+    fn assert_line(self, line: usize) {
+        let loc = self.location();
+        assert!(
+            loc.start.line == line,
+            "expected span at line {}, but it's at {}",
+            line,
+            loc
+        );
+    }
+
     fn into_pm2_span(self) -> Span;
 }
 
+/// Location of a span. See [`SpanExt::location`].
+#[cfg(feature = "span-locations")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanLocation {
+    pub start: LineColumn,
+    pub end: LineColumn,
+}
+
+#[cfg(feature = "span-locations")]
+impl Display for SpanLocation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}..{}:{}",
+            self.start.line, self.start.column, self.end.line, self.end.column
+        )
+    }
+}
+
 impl SpanExt for Span {
     fn into_pm2_span(self) -> Self {
         self
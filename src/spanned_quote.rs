@@ -2,8 +2,20 @@
 //!
 
 mod buffer;
+#[cfg(feature = "codegen")]
+mod codegen;
+mod dump;
+mod literal;
+mod repeat;
+mod source;
 pub use self::buffer::{Location, Quote};
 
+#[doc(hidden)]
+pub mod __private {
+    pub use super::literal::literal;
+    pub use super::repeat::{RepAsIteratorExt, RepIteratorExt};
+}
+
 #[macro_export]
 #[doc(hidden)]
 macro_rules! quoter_location {
@@ -18,23 +30,55 @@ macro_rules! quoter_location {
 
 // ----- Start of variable handling macros.
 
-/// Usage: __sq_handle_vars! { a, b: expression(), c, };
+/// Usage: __sq_handle_vars! { a, b: expression(), #[respan] c, };
 ///
+/// Normalized form is `name: value => method,`, where `method` is used to
+/// append the variable.
 #[doc(hidden)]
 #[macro_export]
 macro_rules! handle_vars_for_quote {
     (
         @NORMALIZED {
             $(
-                $name:ident: $value:expr,
+                $name:ident: $value:expr => $method:ident,
             )*
         },
     ) => {
         $crate::declare_vars_for_quote!(
-            $($name: $value,)*
+            $($name: $value => $method,)*
         );
     };
 
+    (
+        @NORMALIZED {
+            $($norm:tt)*
+        },
+        #[respan] $name:ident $(, $($rest:tt)*)?
+    ) => {
+        $crate::handle_vars_for_quote!(
+            @NORMALIZED {
+                $($norm)*
+                $name: $name => push_tokens_respanned,
+            },
+            $($($rest)*)?
+        )
+    };
+
+    (
+        @NORMALIZED {
+            $($norm:tt)*
+        },
+        #[respan] $name:ident: $value:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::handle_vars_for_quote!(
+            @NORMALIZED {
+                $($norm)*
+                $name: $value => push_tokens_respanned,
+            },
+            $($($rest)*)?
+        )
+    };
+
     (
         @NORMALIZED {
             $($norm:tt)*
@@ -45,7 +89,7 @@ macro_rules! handle_vars_for_quote {
         $crate::handle_vars_for_quote!(
             @NORMALIZED {
                 $($norm)*
-                $name: $name,
+                $name: $name => push_tokens,
             },
             $($rest)*
         )
@@ -60,7 +104,7 @@ macro_rules! handle_vars_for_quote {
         $crate::handle_vars_for_quote!(
             @NORMALIZED {
                 $($norm)*
-                $name: $name,
+                $name: $name => push_tokens,
             },
         )
     };
@@ -75,7 +119,7 @@ macro_rules! handle_vars_for_quote {
         $crate::handle_vars_for_quote!(
             @NORMALIZED {
                 $($norm)*
-                $name: $value,
+                $name: $value => push_tokens,
             },
             $($rest)*
         )
@@ -90,7 +134,7 @@ macro_rules! handle_vars_for_quote {
         $crate::handle_vars_for_quote!(
             @NORMALIZED {
                 $($norm)*
-                $name: $value,
+                $name: $value => push_tokens,
             },
         )
     };
@@ -102,7 +146,7 @@ macro_rules! handle_vars_for_quote {
 macro_rules! declare_vars_for_quote {
     (
         $(
-            $name:ident: $val:expr,
+            $name:ident: $val:expr => $method:ident,
         )*
     ) => {
         $(
@@ -111,15 +155,19 @@ macro_rules! declare_vars_for_quote {
         )*
 
         // This macro quotes only one token at once.
+        //
+        // The token is passed twice, and the second one is used to refer to
+        // the variable. As it comes from the invocation, it resolves to the
+        // innermost binding, including ones declared by `@for`.
         macro_rules! __sq_push_token_custom {
             $(
-                ($tokens:expr, $name) => {
-                    $tokens.push_tokens(&$name);
+                ($tokens:expr, $name, $var:tt) => {
+                    $tokens.$method(&$var);
                 };
             )*
-            // default (stringify + parse)
-            ($tokens:expr, $t:tt) => {
-                $tokens.push_parsed(stringify!($t));
+            // default
+            ($tokens:expr, $t:tt, $_t:tt) => {
+                $crate::__sq_push_token!($tokens, $t);
             };
         }
 
@@ -157,14 +205,156 @@ macro_rules! __sq_quote_tokens_to {
         $crate::__sq_quote_tokens_to!($tokens, $($rest)*);
     }};
 
+    ($tokens:expr, @for ( $($var:ident),+ $(,)? ) sep ( $($sep:tt)* ) { $($inner:tt)* } $($rest:tt)*) => {{
+        $crate::__sq_quote_repeated!($tokens, ($($var),+), ($($sep)*), { $($inner)* });
+        $crate::__sq_quote_tokens_to!($tokens, $($rest)*);
+    }};
+
+    ($tokens:expr, @for ( $($var:ident),+ $(,)? ) { $($inner:tt)* } $($rest:tt)*) => {{
+        $crate::__sq_quote_repeated!($tokens, ($($var),+), (), { $($inner)* });
+        $crate::__sq_quote_tokens_to!($tokens, $($rest)*);
+    }};
+
+    ($tokens:expr, @if ( $cond:expr ) { $($then:tt)* } @else { $($else:tt)* } $($rest:tt)*) => {{
+        if $cond {
+            $tokens.report_loc($crate::quoter_location!());
+            $crate::__sq_quote_tokens_to!($tokens, $($then)*);
+        } else {
+            $tokens.report_loc($crate::quoter_location!());
+            $crate::__sq_quote_tokens_to!($tokens, $($else)*);
+        }
+        $crate::__sq_quote_tokens_to!($tokens, $($rest)*);
+    }};
+
+    ($tokens:expr, @if ( $cond:expr ) { $($then:tt)* } $($rest:tt)*) => {{
+        if $cond {
+            $tokens.report_loc($crate::quoter_location!());
+            $crate::__sq_quote_tokens_to!($tokens, $($then)*);
+        }
+        $crate::__sq_quote_tokens_to!($tokens, $($rest)*);
+    }};
+
+    ($tokens:expr, @span ( $span:expr ) { $($inner:tt)* } $($rest:tt)*) => {{
+        $tokens.with_span($span, $crate::__sq_quote_closure! {
+            $($inner)*
+        });
+        $crate::__sq_quote_tokens_to!($tokens, $($rest)*);
+    }};
+
+    ($tokens:expr, @{ $($expr:tt)* } $($rest:tt)*) => {{
+        $tokens.push_tokens(&{ $($expr)* });
+        $crate::__sq_quote_tokens_to!($tokens, $($rest)*);
+    }};
+
+    ($tokens:expr, @raw ( $($inner:tt)* ) $($rest:tt)*) => {{
+        $crate::__sq_quote_raw_to!($tokens, $($inner)*);
+        $crate::__sq_quote_tokens_to!($tokens, $($rest)*);
+    }};
+
     // If we have to quote one token, check if user declared variable.
     ($tokens:expr, $first:tt $($rest:tt)*) => {
-        __sq_push_token_custom!($tokens, $first);
+        __sq_push_token_custom!($tokens, $first, $first);
 
         $crate::__sq_quote_tokens_to!($tokens, $($rest)*);
     };
 }
 
+/// Same as `__sq_quote_tokens_to!`, but ignores `Vars` and directives.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __sq_quote_raw_to {
+    // Done.
+    ($tokens:expr,) => {{}};
+
+    ($tokens:expr, ( $($inner:tt)* ) $($rest:tt)*) => {{
+        $tokens.push_group(::proc_macro2::Delimiter::Parenthesis, |_tokens: &mut $crate::Quote| {
+            $crate::__sq_quote_raw_to!(_tokens, $($inner)*);
+        });
+        $crate::__sq_quote_raw_to!($tokens, $($rest)*);
+    }};
+
+    ($tokens:expr, { $($inner:tt)* } $($rest:tt)*) => {{
+        $tokens.push_group(::proc_macro2::Delimiter::Brace, |_tokens: &mut $crate::Quote| {
+            $crate::__sq_quote_raw_to!(_tokens, $($inner)*);
+        });
+        $crate::__sq_quote_raw_to!($tokens, $($rest)*);
+    }};
+
+    ($tokens:expr, [ $($inner:tt)* ] $($rest:tt)*) => {{
+        $tokens.push_group(::proc_macro2::Delimiter::Bracket, |_tokens: &mut $crate::Quote| {
+            $crate::__sq_quote_raw_to!(_tokens, $($inner)*);
+        });
+        $crate::__sq_quote_raw_to!($tokens, $($rest)*);
+    }};
+
+    ($tokens:expr, $first:tt $($rest:tt)*) => {{
+        $crate::__sq_push_token!($tokens, $first);
+        $crate::__sq_quote_raw_to!($tokens, $($rest)*);
+    }};
+}
+
+/// Quotes one token which is not a group, without parsing it at runtime if
+/// possible.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __sq_push_token {
+    // This also matches keywords, including `true` and `false`.
+    ($tokens:expr, $t:ident) => {
+        $tokens.push_ident(stringify!($t));
+    };
+
+    ($tokens:expr, $t:lifetime) => {
+        $tokens.push_lifetime(stringify!($t));
+    };
+
+    // `literal` fragment would try to parse `-` as a negative literal.
+    ($tokens:expr, -) => {
+        $tokens.push_op("-");
+    };
+
+    ($tokens:expr, $t:literal) => {
+        $tokens.push_literal($crate::spanned_quote::__private::literal(stringify!($t)));
+    };
+
+    ($tokens:expr, $t:tt) => {
+        $tokens.push_op(stringify!($t));
+    };
+}
+
+/// Quotes `{ $inner }` once for each item of `Vars`, which are iterated in
+/// lockstep, and quotes `( $sep )` between them.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __sq_quote_repeated {
+    ($tokens:expr, ($($var:ident),+), ($($sep:tt)*), { $($inner:tt)* }) => {{
+        #[allow(unused_imports)]
+        use $crate::spanned_quote::__private::{RepAsIteratorExt as _, RepIteratorExt as _};
+
+        $(
+            #[allow(non_snake_case, unused_mut)]
+            let mut $var = $var.__sq_rep();
+        )+
+
+        let mut first = true;
+        loop {
+            $(
+                #[allow(non_snake_case)]
+                let $var = match $var.next() {
+                    Some(item) => item,
+                    None => break,
+                };
+            )+
+
+            if !first {
+                $crate::__sq_quote_tokens_to!($tokens, $($sep)*);
+            }
+            first = false;
+
+            $crate::__sq_quote_tokens_to!($tokens, $($inner)*);
+        }
+    }};
+}
+
 /// ide-friendly quasi quotting.
 ///
 ///# Syntax
@@ -190,10 +380,98 @@ macro_rules! __sq_quote_tokens_to {
 ///
 ///Note that `Vars{}` is required even if there's no variable.
 ///
+/// Variables are appended without respanning. Mark a variable with
+/// `#[respan]` to respan its tokens like tokens of the template, using
+/// [`Quote::push_tokens_respanned`].
+///
+///```rust,ignore
+/// smart_quote!(field.ty.span() => Vars{ #[respan] Path: &cached_path }, {
+///     impl Foo for Path {}
+/// })
+///```
+///
 ///## Tokens
 /// As parsers for syntax highligters implement error recovery,
 ///  tokens are wrapped in block or paren like `{ tokens.. }`/ `( tokens.. )`.
 ///
+///## Span
+/// `smart_quote!(span => Vars{ .. }, { .. })` uses `span` to respan tokens
+/// instead of the span of `Quote`. `span` can be any value which implements
+/// `Respan`.
+///
+/// `@span(span) { tokens.. }` uses `span` only for `tokens..`, and restores
+/// the previous one afterwards.
+///
+///```rust,ignore
+/// smart_quote!(field.ty.span() => Vars{ Type: &field.ty }, {
+///     impl Foo for Type {
+///         @span(Span::call_site()) {
+///             fn foo() {}
+///         }
+///     }
+/// })
+///```
+///
+///## Interpolation
+/// `@{ expr }` appends value of `expr` without respanning, just like
+/// variables. `expr` can be any expression which implements `ToTokens`.
+///
+/// `@raw( tokens.. )` quotes `tokens..` literally, even if they match
+/// a variable or a directive.
+///
+///```rust,ignore
+/// smart_quote!(Vars{ field }, {
+///     fn @{ field.new_ident_with(|f| format!("get_{}", f)) }(&self) -> u32 {
+///         self.@raw(field).len()
+///     }
+/// })
+///```
+///
+///## Conditional
+/// `@if (cond) { tokens.. } @else { tokens.. }` evaluates `cond` when the
+/// closure is invoked, and quotes only one of the branches. `@else` is
+/// optional, and `@if` can be nested inside of branches.
+///
+///```rust,ignore
+/// smart_quote!(Vars{ Type: &input.ident }, {
+///     impl Foo for Type {
+///         @if (is_unit) {
+///             fn new() -> Self { Type }
+///         } @else {
+///             fn new() -> Self { Default::default() }
+///         }
+///     }
+/// })
+///```
+///
+///## Repetition
+/// `@for (a, b) { tokens.. }` quotes `tokens..` once for each item of `a` and
+/// `b`, which are iterated at the same time. Inside the block, `a` and `b`
+/// refer to the current item. Repetition stops when any of them is exhausted.
+///
+/// Variables should be declared in `Vars`, and their values can be either an
+/// iterator or a reference to a collection.
+///
+/// `@for (a) sep(,) { tokens.. }` also quotes `,` between items, without a
+/// trailing one. Any tokens can be used as a separator.
+///
+///```rust,ignore
+/// smart_quote!(Vars{
+///     field: fields.iter().map(|f| &f.ident),
+///     ty: fields.iter().map(|f| &f.ty),
+/// }, {
+///     struct Foo {
+///         @for (field, ty) {
+///             field: ty,
+///         }
+///     }
+///
+///     fn foo() -> (@for (ty) sep(,) { ty }) {
+///         unimplemented!()
+///     }
+/// })
+///```
+///
 ///# Example
 ///
 ///```rust,ignore
@@ -213,6 +491,12 @@ macro_rules! __sq_quote_tokens_to {
 ///     yield ();
 /// })
 ///```
+///
+///# Recursion limit
+/// Each token of a template is handled by a recursive macro call, so large
+/// templates may require `#![recursion_limit]`. With feature `macros`, this is
+/// implemented as a proc-macro instead, which does not have the limit.
+#[cfg(not(feature = "macros"))]
 #[macro_export]
 macro_rules! smart_quote {
     (
@@ -261,6 +545,26 @@ macro_rules! smart_quote {
     ) => {
         $crate::smart_quote!(Vars {}, { $($tokens)* })
     };
+
+    (
+        $span:expr => $($rest:tt)*
+    ) => {{
+        |_tokens: &mut $crate::Quote| {
+            _tokens.with_span($span, $crate::smart_quote!($($rest)*));
+        }
+    }};
+}
+
+/// Quasi-quotting, implemented by `pmutil-macros`.
+///
+/// This passes `$crate` to the proc-macro, so generated code does not depend
+/// on the name of `pmutil`.
+#[cfg(feature = "macros")]
+#[macro_export]
+macro_rules! smart_quote {
+    ( $($tokens:tt)* ) => {
+        $crate::__smart_quote!($crate; $($tokens)*)
+    };
 }
 
 #[doc(hidden)]
@@ -275,6 +579,8 @@ macro_rules! __sq_quote_closure {
 }
 
 /// Shortcut for `Quote::new_call_site().quote_with(smart_quote!( $tokens ))`
+///
+/// Like `smart_quote!`, span can be specified with `q!(span => Vars{ .. }, { .. })`.
 #[macro_export]
 macro_rules! q {
     ( $($tokens:tt)* ) => {{
@@ -1,22 +1,34 @@
+use super::dump;
+use super::source::{self, Sources};
+use crate::diag::Errors;
+use crate::pretty;
 use crate::respan::{self, Respan};
-use proc_macro2::{Delimiter, Group, Ident, Span, TokenStream, TokenTree};
+use crate::span_dump;
+use proc_macro2::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
 use quote::{ToTokens, TokenStreamExt};
-use std::collections::HashSet;
+use std::any::Any;
 use std::env;
 use std::fmt::{self, Display, Formatter, Write};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
 use syn::parse::Parse;
 
 /// Buffer for quasi quotting.
 pub struct Quote {
     tts: TokenStream,
-    span: Option<Box<(dyn Respan + 'static)>>,
-    /// Location of smart_quote! invokations.
+    span: Option<Box<dyn Respan + 'static>>,
+    /// Location of the current smart_quote! invokation.
+    loc: Option<Location>,
+    /// Location of smart_quote! invokations which emitted each token.
     /// Used for error reporting.
-    sources: HashSet<Location>,
+    sources: Sources,
+    /// Errors emitted as `compile_error!` instead of `tts`.
+    errors: Vec<syn::Error>,
+    /// Hash of macro input. Used to name dumped files.
+    input: Option<u64>,
 }
 
-const INVALID_SPAN_STATE: &str = "Span is in invalid state.
-Closure provided to push_group should not panic.";
+const INVALID_SPAN_STATE: &str = "Span is in invalid state.";
 
 /// Location of `smart_quote!` macro invocation.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -38,7 +50,10 @@ impl Quote {
         Quote {
             span: Some(Box::new(span)),
             tts: TokenStream::new(),
+            loc: None,
             sources: Default::default(),
+            errors: Default::default(),
+            input: None,
         }
     }
 
@@ -51,6 +66,31 @@ impl Quote {
         Self::new(Span::call_site())
     }
 
+    /// Shorthand for
+    ///
+    /// ```rust,ignore
+    /// Quote::new(Span::mixed_site())
+    /// ```
+    ///
+    /// Local variables and labels in the template are hygienic, while
+    /// variables in `Vars` keep their own spans.
+    pub fn new_mixed_site() -> Self {
+        Self::new(Span::mixed_site())
+    }
+
+    /// Shorthand for
+    ///
+    /// ```rust,ignore
+    /// Quote::new(Span::def_site())
+    /// ```
+    ///
+    /// Requires nightly compiler and can be used only inside procedural
+    /// macros.
+    #[cfg(feature = "nightly")]
+    pub fn new_def_site() -> Self {
+        Self::new(respan::def_site())
+    }
+
     /// Shorthand for
     ///
     /// ```rust,ignore
@@ -78,51 +118,164 @@ impl Quote {
 }
 
 impl Quote {
+    /// Records macro input, which is used to name files dumped into
+    /// `PMUTIL_DUMP_DIR`.
+    ///
+    /// If environment variable `PMUTIL_DUMP_DIR` is set, `self` is written
+    /// into a file under the directory when it's parsed or converted into
+    /// `proc_macro::TokenStream`. The file is named after the location of
+    /// `smart_quote!` invocation and a hash of the input, so expansions can be
+    /// compared between builds.
+    pub fn for_input(mut self, input: &dyn ToTokens) -> Self {
+        let mut tts = TokenStream::new();
+        input.to_tokens(&mut tts);
+        self.input = Some(dump::hash(&tts.to_string()));
+        self
+    }
+
+    /// Location of the first `smart_quote!` invocation which emitted tokens.
+    pub(super) fn origin(&self) -> Option<Location> {
+        self.sources.locations().first().copied()
+    }
+
+    /// Writes `tts` into `PMUTIL_DUMP_DIR`, if it's set.
+    fn dump(&self, tts: &TokenStream) {
+        dump::dump(self.origin(), self.input, tts);
+    }
+
     /// Parse tokens as `Node`.
     /// Panics if parsing failed.
     pub fn parse<Node>(self) -> Node
     where
         Node: Parse,
     {
-        // TODO: Use span to report error.
-        let Quote { tts, sources, .. } = self;
-
-        let debug_tts = if env::var("DBG_DUMP").is_ok() {
-            Some(tts.clone())
-        } else {
-            None
+        let (err, index) = match combine(self.errors.iter().cloned()) {
+            Some(err) => (err, None),
+            None => match self.parse_tts() {
+                Ok(node) => return node,
+                Err(err) => err,
+            },
         };
 
-        syn::parse2(tts).unwrap_or_else(|err| {
-            let debug_tts: &dyn Display = match debug_tts {
-                Some(ref tts) => tts,
-                None => {
-                    &"To get code failed to parse,
- please set environment variable `DBG_DUMP` and run in again"
-                }
-            };
+        let debug_tts = match env::var("DBG_DUMP") {
+            Ok(ref mode) if mode == "spans" => span_dump::print(&self.tts, None, index),
+            Ok(..) => pretty::print(&self.tts, index),
+            Err(..) => String::from(
+                "To get code failed to parse,
+ please set environment variable `DBG_DUMP` and run in again.
+ Set it to `spans` to print spans of tokens instead.",
+            ),
+        };
 
-            let notes = {
-                let mut b = String::from("Note: quasi quotting was invoked from:\n");
-                for src in &sources {
-                    writeln!(b, "       {src}").unwrap();
-                }
-                b
-            };
+        let mut msg = String::new();
+        for err in err {
+            writeln!(msg, "{err}").unwrap();
+        }
 
-            panic!(
-                "Quote::parse() failed.
-                {notes}
-Error from syn: {err}
+        panic!(
+            "Quote::parse() failed.
+Error from syn: {msg}
     >>>>>
-        {debug_tts}
+{debug_tts}
     <<<<<",
-                notes = notes,
-                err = err,
-                debug_tts = debug_tts
-            )
+            msg = msg,
+            debug_tts = debug_tts
+        )
+    }
+
+    /// Parse tokens as `Node`.
+    ///
+    /// Unlike [`Quote::parse`], this does not panic. Each message of the
+    /// returned error keeps the span from `syn` and notes where the quasi
+    /// quotting which emitted the bad token was invoked from.
+    pub fn try_parse<Node>(self) -> syn::Result<Node>
+    where
+        Node: Parse,
+    {
+        match combine(self.errors.iter().cloned()) {
+            Some(err) => Err(err),
+            None => self.parse_tts().map_err(|(err, _)| err),
+        }
+    }
+
+    /// Parse tokens as `Node` to check if they are valid.
+    ///
+    /// If parsing failed, the error is recorded and `self` will be converted
+    /// into `compile_error!` instead of the tokens.
+    pub fn check<Node>(mut self) -> Self
+    where
+        Node: Parse,
+    {
+        if self.errors.is_empty() {
+            if let Err((err, _)) = self.parse_tts::<Node>() {
+                self.errors.push(err);
+            }
+        }
+
+        self
+    }
+
+    /// On failure, returns the error with notes and index of the bad token.
+    fn parse_tts<Node>(&self) -> Result<Node, (syn::Error, Option<usize>)>
+    where
+        Node: Parse,
+    {
+        self.dump(&self.tts);
+
+        syn::parse2(self.tts.clone()).map_err(|err| {
+            let index = source::find_error::<Node>(&self.tts, &err);
+            let err = match index.map(|i| self.sources.find(i)) {
+                Some(Some(loc)) => with_notes(err, &[loc]),
+                // The bad token is not from a template.
+                Some(None) => err,
+                None => with_notes(err, &self.sources.locations()),
+            };
+            (err, index)
         })
     }
+
+    /// Formats tokens into indented rust source.
+    ///
+    /// This works for fragments which are not valid items or expressions.
+    pub fn to_pretty_string(&self) -> String {
+        pretty::print(&self.tts, None)
+    }
+}
+
+/// Adds locations of `smart_quote!` invocations to each message of `err`.
+fn with_notes(err: syn::Error, locs: &[Location]) -> syn::Error {
+    let notes = {
+        let mut b = String::from("Note: quasi quotting was invoked from:");
+        for loc in locs {
+            write!(b, "\n       {loc}").unwrap();
+        }
+        b
+    };
+
+    let errors = err
+        .into_iter()
+        .map(|err| syn::Error::new(err.span(), format!("{err}\n{notes}")));
+    combine(errors).expect("syn::Error always contains a message")
+}
+
+fn combine<I>(errors: I) -> Option<syn::Error>
+where
+    I: IntoIterator<Item = syn::Error>,
+{
+    errors.into_iter().reduce(|mut acc, err| {
+        acc.combine(err);
+        acc
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg
+    } else {
+        "quasi quotting panicked"
+    }
 }
 
 /// Methods for quasi-quotting.
@@ -130,22 +283,79 @@ impl Quote {
     #[doc(hidden)]
     /// Reports location of `smart_quote!` invocation.
     pub fn report_loc(&mut self, loc: Location) {
-        self.sources.insert(loc);
+        self.loc = Some(loc);
     }
 
+    /// Runs `quote` on `self`.
+    ///
+    /// Location reported by `smart_quote!` is reset afterwards, so tokens
+    /// pushed by hand are not attributed to the template.
+    ///
+    /// If `quote` panics, tokens appended by it are discarded and the panic
+    /// is recorded as an error, which is emitted as `compile_error!` when
+    /// `self` is converted into a `TokenStream`.
     pub fn quote_with<F>(mut self, quote: F) -> Self
     where
         F: FnOnce(&mut Self),
     {
-        (quote)(&mut self);
+        // Tokens are appended to an empty stream, so the ones already
+        // generated are neither copied nor discarded on panic.
+        let tts = mem::take(&mut self.tts);
+        let len = self.sources.len();
+        let loc = self.loc;
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| (quote)(&mut self)));
+        let new = mem::replace(&mut self.tts, tts);
+
+        if let Err(payload) = res {
+            let span = match self.span {
+                Some(ref span) => span.next_span(),
+                None => {
+                    self.span = Some(Box::new(Span::call_site()));
+                    Span::call_site()
+                }
+            };
+            let err = syn::Error::new(span, panic_message(&*payload));
+            self.sources.truncate(len);
+            let err = match self.loc {
+                Some(loc) => with_notes(err, &[loc]),
+                None => with_notes(err, &self.sources.locations()),
+            };
+            self.errors.push(err);
+        } else {
+            self.tts.extend(new);
+        }
+        self.loc = loc;
+
         self
     }
 
+    /// Runs `quote` with `span` as the span of `self`, and restores the
+    /// previous one (and the location reported by `smart_quote!`) afterwards,
+    /// even if `quote` panics.
+    pub fn with_span<S, F>(&mut self, span: S, quote: F)
+    where
+        S: Respan + 'static,
+        F: FnOnce(&mut Self),
+    {
+        let prev = self.span.replace(Box::new(span));
+        let loc = self.loc;
+        let res = panic::catch_unwind(AssertUnwindSafe(|| quote(self)));
+        self.span = prev;
+        self.loc = loc;
+
+        if let Err(payload) = res {
+            panic::resume_unwind(payload)
+        }
+    }
+
     /// Parse `token` and append it to `self`.
     pub fn push_parsed(&mut self, token: &str) {
         let Quote {
             ref mut span,
             ref mut tts,
+            ref mut sources,
+            loc,
             ..
         } = *self;
 
@@ -154,44 +364,168 @@ impl Quote {
             .expect("Failed to parse token to quote")
             .into_iter()
             .map(|tt| span.as_ref().expect(INVALID_SPAN_STATE).respan(tt))
-            .for_each(|tt| tts.append(tt));
+            .for_each(|tt| {
+                sources.record(loc, source::count_tt(&tt));
+                tts.append(tt)
+            });
+    }
+
+    /// Respan `tt` and append it to `self`.
+    fn push_respanned(&mut self, tt: TokenTree) {
+        let tt = self.span.as_ref().expect(INVALID_SPAN_STATE).respan(tt);
+        self.push_tt(tt)
+    }
+
+    /// Respan identifier and append it to `self`. `ident` can be a raw
+    /// identifier like `r#type`.
+    pub fn push_ident(&mut self, ident: &str) {
+        let ident = match ident.strip_prefix("r#") {
+            Some(raw) => Ident::new_raw(raw, Span::call_site()),
+            None => Ident::new(ident, Span::call_site()),
+        };
+        self.push_respanned(TokenTree::Ident(ident))
+    }
+
+    /// Respan punctuation and append it to `self`.
+    pub fn push_punct(&mut self, ch: char, spacing: Spacing) {
+        self.push_respanned(TokenTree::Punct(Punct::new(ch, spacing)))
+    }
+
+    /// Respan operator like `::` or `+=` and append it to `self`.
+    ///
+    /// Falls back to [`Quote::push_parsed`] if `op` is not punctuations.
+    pub fn push_op(&mut self, op: &str) {
+        if !op.chars().all(|c| c.is_ascii_punctuation() && c != '_') {
+            return self.push_parsed(op);
+        }
+
+        let mut chars = op.chars().peekable();
+        while let Some(ch) = chars.next() {
+            let spacing = if chars.peek().is_some() {
+                Spacing::Joint
+            } else {
+                Spacing::Alone
+            };
+            self.push_punct(ch, spacing);
+        }
+    }
+
+    /// Respan lifetime like `'a` and append it to `self`.
+    pub fn push_lifetime(&mut self, lifetime: &str) {
+        self.push_punct('\'', Spacing::Joint);
+        self.push_ident(lifetime.trim_start_matches('\''));
+    }
+
+    /// Respan literal and append it to `self`.
+    pub fn push_literal(&mut self, lit: Literal) {
+        self.push_respanned(TokenTree::Literal(lit))
     }
 
     /// Append `tt` to `self`.
     pub fn push_tt(&mut self, tt: TokenTree) {
+        self.sources.record(self.loc, source::count_tt(&tt));
         self.tts.append(tt)
     }
 
     /// Respan symbol and append it to `self`.
     pub fn push_sym(&mut self, term: &str) {
-        let span = self.next_span();
-        self.push_tt(TokenTree::Ident(Ident::new(term, span)))
-    }
-
-    fn next_span(&self) -> Span {
-        self.span.as_ref().expect(INVALID_SPAN_STATE).next_span()
+        self.push_respanned(TokenTree::Ident(Ident::new(term, Span::call_site())))
     }
 
     /// Respan and append `TokenStream::Group`
+    ///
+    /// Span of delimiters is taken from the current [`Respan`] strategy, before
+    /// quoting the contents.
     pub fn push_group<F>(&mut self, delim: Delimiter, child: F)
     where
         F: FnOnce(&mut Quote),
     {
-        //TODO: Exception safety
-        let span = self.span.take().expect(INVALID_SPAN_STATE);
-        let mut sub = Quote::new(span);
-        child(&mut sub);
-        self.sources.extend(sub.sources);
+        let group = TokenTree::Group(Group::new(delim, TokenStream::new()));
+        let span = self
+            .span
+            .as_ref()
+            .expect(INVALID_SPAN_STATE)
+            .respan(group)
+            .span();
+
+        self.push_group_spanned(delim, span, child)
+    }
+
+    /// Append `TokenStream::Group` with delimiters at `span`. Contents are
+    /// respanned as usual.
+    ///
+    /// Stable `proc_macro` does not allow setting open and close delimiters
+    /// separately, so use [`DelimSpan::join`] to reuse delimiters of input,
+    /// like `Quote::push_group_spanned(Delimiter::Brace, brace.span.join(), ..)`
+    /// for `syn::token::Brace`.
+    ///
+    /// [`DelimSpan::join`]: proc_macro2::extra::DelimSpan::join
+    pub fn push_group_spanned<F>(&mut self, delim: Delimiter, span: Span, child: F)
+    where
+        F: FnOnce(&mut Quote),
+    {
+        let strategy = self.span.take().expect(INVALID_SPAN_STATE);
+        let mut sub = Quote::new(strategy);
+        sub.loc = self.loc;
+        let res = panic::catch_unwind(AssertUnwindSafe(|| child(&mut sub)));
 
+        // Strategy is restored even if `child` panics, so the error is
+        // reported at the next span.
         debug_assert!(self.span.is_none());
-        self.span = Some(sub.span.expect(INVALID_SPAN_STATE));
+        self.span = sub.span.take();
+        if let Err(payload) = res {
+            panic::resume_unwind(payload)
+        }
+        self.errors.extend(sub.errors);
+
+        let mut group = Group::new(delim, sub.tts);
+        group.set_span(span);
 
-        self.push_tt(TokenTree::Group(Group::new(delim, sub.tts)))
+        // Group is counted before its contents.
+        self.sources.record(self.loc, 1);
+        self.sources.append(sub.sources);
+        self.tts.append(TokenTree::Group(group))
     }
 
     /// Appends node into `self` **without respanning**.
     pub fn push_tokens<T: ?Sized + ToTokens>(&mut self, node: &T) {
-        node.to_tokens(&mut self.tts);
+        let tokens = node.to_token_stream();
+        self.sources.record(self.loc, source::count(&tokens));
+        self.tts.extend(tokens);
+    }
+
+    /// Appends node into `self`, respanning its tokens (including tokens inside
+    /// of groups) like tokens of templates.
+    pub fn push_tokens_respanned<T: ?Sized + ToTokens>(&mut self, node: &T) {
+        let strategy = self.span.as_ref().expect(INVALID_SPAN_STATE);
+        let tokens = respan::respan_tokens(node.to_token_stream(), &**strategy);
+        self.sources.record(self.loc, source::count(&tokens));
+        self.tts.extend(tokens);
+    }
+
+    /// Appends all diagnostics in `errors` to `self`, next to tokens which are
+    /// already generated.
+    pub fn push_errors(&mut self, errors: Errors) {
+        let tokens = TokenStream::from(errors);
+        self.sources.record(self.loc, source::count(&tokens));
+        self.tts.extend(tokens);
+    }
+
+    /// Appends nodes into `self` **without respanning**, separated by `sep`.
+    ///
+    /// `sep` is parsed and respanned like [`Quote::push_parsed`], and is not
+    /// appended after the last node.
+    pub fn push_separated<I>(&mut self, nodes: I, sep: &str)
+    where
+        I: IntoIterator,
+        I::Item: ToTokens,
+    {
+        for (i, node) in nodes.into_iter().enumerate() {
+            if i != 0 {
+                self.push_parsed(sep);
+            }
+            self.push_tokens(&node);
+        }
     }
 }
 
@@ -199,29 +533,47 @@ impl IntoIterator for Quote {
     type IntoIter = <TokenStream as IntoIterator>::IntoIter;
     type Item = <TokenStream as IntoIterator>::Item;
 
+    /// Iterates over `compile_error!` invocations instead of the tokens if
+    /// `self` contains errors.
     fn into_iter(self) -> Self::IntoIter {
-        self.tts.into_iter()
+        TokenStream::from(self).into_iter()
     }
 }
 
+/// Converts `quote` into `compile_error!` invocations if it contains errors.
 impl From<Quote> for TokenStream {
     fn from(quote: Quote) -> Self {
-        quote.tts
+        match combine(quote.errors) {
+            Some(err) => err.to_compile_error(),
+            None => quote.tts,
+        }
     }
 }
 
+#[cfg(feature = "proc-macro")]
 impl From<Quote> for proc_macro::TokenStream {
     fn from(quote: Quote) -> Self {
-        TokenStream::from(quote).into()
+        let loc = quote.origin();
+        let input = quote.input;
+
+        let tts = TokenStream::from(quote);
+        dump::dump(loc, input, &tts);
+        tts.into()
     }
 }
 
 impl ToTokens for Quote {
     fn to_tokens(&self, dst: &mut TokenStream) {
-        self.tts.to_tokens(dst)
+        if self.errors.is_empty() {
+            self.tts.to_tokens(dst)
+        } else {
+            for err in &self.errors {
+                err.to_compile_error().to_tokens(dst)
+            }
+        }
     }
 
     fn into_token_stream(self) -> TokenStream {
-        self.tts
+        self.into()
     }
 }
@@ -0,0 +1,86 @@
+//! Writes `Quote` into rust source files, for build scripts.
+
+use super::Quote;
+use crate::comment::comment;
+use quote::ToTokens;
+use std::env;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use syn::{Expr, Lit, Meta};
+
+impl Quote {
+    /// Writes `self` into `$OUT_DIR/name`, and returns path to the file.
+    ///
+    /// See [`Quote::write_rust_file_at`] for details.
+    pub fn write_rust_file<P>(self, name: P) -> io::Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = env::var_os("OUT_DIR")
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "OUT_DIR is not set"))?;
+
+        let path = Path::new(&dir).join(name);
+        self.write_rust_file_at(&path)?;
+        Ok(path)
+    }
+
+    /// Parses `self` as a `syn::File`, and writes it into `path` with
+    /// a header.
+    ///
+    /// The file is formatted like [`Quote::to_pretty_string`], unless the
+    /// formatted code fails to parse back into the same tokens.
+    ///
+    /// The file is not touched if it's not changed, so it does not trigger
+    /// rebuilds. Returns `true` if the file is written.
+    pub fn write_rust_file_at<P>(self, path: P) -> io::Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        let header = match self.origin() {
+            Some(loc) => comment(format!(" @generated by pmutil from {loc}. Do not edit.")),
+            None => comment(" @generated by pmutil. Do not edit."),
+        };
+        let pretty = self.to_pretty_string();
+        let file = self
+            .try_parse::<syn::File>()
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err.to_string()))?;
+
+        // The pretty printer works on tokens, so check that the output means
+        // the same thing before writing it.
+        let tokens = file.to_token_stream().to_string();
+        let body = match syn::parse_str::<syn::File>(&pretty) {
+            Ok(ref printed) if printed.to_token_stream().to_string() == tokens => pretty,
+            _ => tokens,
+        };
+
+        let mut content = String::new();
+        if let Meta::NameValue(ref meta) = header.meta {
+            if let Expr::Lit(ref lit) = meta.value {
+                if let Lit::Str(ref s) = lit.lit {
+                    for line in s.value().lines() {
+                        content.push_str("//");
+                        content.push_str(line);
+                        content.push('\n');
+                    }
+                }
+            }
+        }
+        content.push('\n');
+        content.push_str(&body);
+        content.push('\n');
+
+        match fs::read_to_string(path) {
+            Ok(ref prev) if *prev == content => return Ok(false),
+            _ => {}
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, content)?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,59 @@
+//! Dumps expansions into files, for debugging.
+
+use super::Location;
+use crate::pretty;
+use proc_macro2::{Span, TokenStream};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Environment variable for the directory to dump expansions into.
+const DUMP_DIR: &str = "PMUTIL_DUMP_DIR";
+
+/// Writes `tts` into a file under `PMUTIL_DUMP_DIR`, if it's set.
+///
+/// The file is named after `loc` and `input`, which is a hash of macro input.
+/// If `input` is not known, a hash of the call site is used instead.
+pub(super) fn dump(loc: Option<Location>, input: Option<u64>, tts: &TokenStream) {
+    let dir = match env::var_os(DUMP_DIR) {
+        Some(dir) => PathBuf::from(dir),
+        None => return,
+    };
+
+    let input = input.unwrap_or_else(|| hash(&format!("{:?}", Span::call_site())));
+    let name = match loc {
+        Some(loc) => format!(
+            "{}-{}-{}-{:016x}.rs",
+            sanitize(loc.file_name),
+            loc.line,
+            loc.col,
+            input
+        ),
+        None => format!("unknown-{input:016x}.rs"),
+    };
+
+    let res = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(dir.join(&name), pretty::print(tts, None) + "\n"));
+    if let Err(err) = res {
+        eprintln!(
+            "pmutil: failed to dump `{}` into {}: {}",
+            name,
+            dir.display(),
+            err
+        );
+    }
+}
+
+/// FNV-1a, which is stable between builds.
+pub(super) fn hash(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn sanitize(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
@@ -0,0 +1,33 @@
+//! Helpers for literals in `smart_quote!`.
+//!
+//! `macro_rules!` cannot tell the kind of a literal, and using a literal as
+//! a value makes rustc type-check it (e.g. integers larger than `u128` are
+//! rejected), so literals are created from their source text. Common ones
+//! are created directly, and others are parsed.
+
+use proc_macro2::Literal;
+
+/// Creates a literal from `repr`, which is `stringify!` of the literal.
+pub fn literal(repr: &str) -> Literal {
+    if !repr.contains('\\') {
+        if let Some(s) = repr.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            return Literal::string(s);
+        }
+
+        if let Some(s) = repr.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+            let mut chars = s.chars();
+            if let (Some(c), None) = (chars.next(), chars.next()) {
+                return Literal::character(c);
+            }
+        }
+    }
+
+    if repr.bytes().all(|b| b.is_ascii_digit()) {
+        match repr.parse::<u64>() {
+            Ok(v) if v.to_string() == repr => return Literal::u64_unsuffixed(v),
+            _ => {}
+        }
+    }
+
+    repr.parse().expect("Failed to parse literal to quote")
+}
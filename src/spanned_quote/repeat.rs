@@ -0,0 +1,32 @@
+//! Helpers for repetition in `smart_quote!`.
+//!
+//! Values of repeated `Vars` may be either an iterator or something which can
+//! be iterated by reference, like `Vec<T>` or `Punctuated<T, P>`.
+
+/// Used for values which are already an iterator.
+pub trait RepIteratorExt: Iterator + Sized {
+    fn __sq_rep(self) -> Self {
+        self
+    }
+}
+
+impl<T: Iterator> RepIteratorExt for T {}
+
+/// Used for values which can be iterated by reference.
+pub trait RepAsIteratorExt<'q> {
+    type Iter: Iterator;
+
+    fn __sq_rep(&'q self) -> Self::Iter;
+}
+
+impl<'q, T> RepAsIteratorExt<'q> for T
+where
+    T: 'q + ?Sized,
+    &'q T: IntoIterator,
+{
+    type Iter = <&'q T as IntoIterator>::IntoIter;
+
+    fn __sq_rep(&'q self) -> Self::Iter {
+        self.into_iter()
+    }
+}
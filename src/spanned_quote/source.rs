@@ -0,0 +1,166 @@
+//! Provenance of tokens in `Quote`.
+//!
+//! Tokens are indexed in depth-first order, and a group is counted before its
+//! contents.
+
+use super::Location;
+use proc_macro2::{Group, TokenStream, TokenTree};
+use quote::TokenStreamExt;
+use syn::parse::Parse;
+
+/// Range of tokens emitted by one `smart_quote!` invocation.
+#[derive(Debug, Clone, Copy)]
+struct Source {
+    start: usize,
+    end: usize,
+    loc: Location,
+}
+
+/// Records which `smart_quote!` invocation emitted each token.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Sources {
+    len: usize,
+    ranges: Vec<Source>,
+}
+
+impl Sources {
+    /// Records `n` tokens emitted by `loc`.
+    pub fn record(&mut self, loc: Option<Location>, n: usize) {
+        let start = self.len;
+        self.len += n;
+
+        if let Some(loc) = loc {
+            self.push(Source {
+                start,
+                end: self.len,
+                loc,
+            });
+        }
+    }
+
+    /// Appends `other`, which starts right after the last recorded token.
+    pub fn append(&mut self, other: Sources) {
+        let offset = self.len;
+        self.len += other.len;
+
+        for src in other.ranges {
+            self.push(Source {
+                start: src.start + offset,
+                end: src.end + offset,
+                loc: src.loc,
+            });
+        }
+    }
+
+    /// Number of recorded tokens.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Forgets tokens recorded after the first `len` tokens.
+    pub fn truncate(&mut self, len: usize) {
+        self.len = len;
+        self.ranges.retain(|src| src.start < len);
+        if let Some(last) = self.ranges.last_mut() {
+            last.end = last.end.min(len);
+        }
+    }
+
+    fn push(&mut self, src: Source) {
+        if src.start == src.end {
+            return;
+        }
+
+        match self.ranges.last_mut() {
+            Some(last) if last.loc == src.loc && last.end == src.start => last.end = src.end,
+            _ => self.ranges.push(src),
+        }
+    }
+
+    /// Returns the location which emitted the `index`-th token.
+    pub fn find(&self, index: usize) -> Option<Location> {
+        self.ranges
+            .iter()
+            .find(|src| src.start <= index && index < src.end)
+            .map(|src| src.loc)
+    }
+
+    /// Returns all locations, in the order they emitted tokens.
+    pub fn locations(&self) -> Vec<Location> {
+        let mut locs = Vec::<Location>::new();
+        for src in &self.ranges {
+            if !locs.contains(&src.loc) {
+                locs.push(src.loc);
+            }
+        }
+        locs
+    }
+}
+
+/// Counts tokens in `tts`, including tokens inside of groups.
+pub(super) fn count(tts: &TokenStream) -> usize {
+    tts.clone().into_iter().map(|tt| count_tt(&tt)).sum()
+}
+
+pub(super) fn count_tt(tt: &TokenTree) -> usize {
+    match *tt {
+        TokenTree::Group(ref g) => 1 + count(&g.stream()),
+        _ => 1,
+    }
+}
+
+/// Returns the first `n` tokens of `tts`. Groups are closed right after the
+/// last token.
+fn truncate(tts: TokenStream, n: &mut usize) -> TokenStream {
+    let mut buf = TokenStream::new();
+
+    for tt in tts {
+        if *n == 0 {
+            break;
+        }
+        *n -= 1;
+
+        match tt {
+            TokenTree::Group(g) => {
+                let mut group = Group::new(g.delimiter(), truncate(g.stream(), n));
+                group.set_span(g.span());
+                buf.append(group);
+            }
+            tt => buf.append(tt),
+        }
+    }
+
+    buf
+}
+
+/// Finds index of the token which caused `err`.
+///
+/// As spans of generated tokens are not unique, this searches for the
+/// shortest prefix of `tts` which fails with the same error.
+pub(super) fn find_error<Node>(tts: &TokenStream, err: &syn::Error) -> Option<usize>
+where
+    Node: Parse,
+{
+    let msg = err.to_string();
+    let fails_at = |mut n: usize| match syn::parse2::<Node>(truncate(tts.clone(), &mut n)) {
+        Ok(..) => false,
+        Err(err) => err.to_string() == msg,
+    };
+
+    // Error is not caused by a specific token.
+    if fails_at(0) {
+        return None;
+    }
+
+    let (mut lo, mut hi) = (0, count(tts));
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if fails_at(mid) {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+
+    Some(hi - 1)
+}
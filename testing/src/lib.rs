@@ -1,6 +1,8 @@
 extern crate proc_macro;
 
 use pmutil::prelude::*;
+use pmutil::respan::{self, ByIdent, Cycle, Sequential};
+use proc_macro2::Span;
 
 #[proc_macro]
 pub fn fn_like(_: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -54,3 +56,281 @@ pub fn vars_1(_: proc_macro::TokenStream) -> proc_macro::TokenStream {
         }))
         .into()
 }
+
+#[proc_macro]
+pub fn repeat(_: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let names = ["a", "b", "c"]
+        .iter()
+        .map(|name| Span::call_site().new_ident(format!("output_repeat_{name}")));
+    let values = vec!["foo", "bar"];
+
+    q!(Vars { names, values }, {
+        @for (names, values) {
+            fn names() -> &'static str {
+                values
+            }
+        }
+    })
+    .into()
+}
+
+#[proc_macro]
+pub fn repeat_sep(_: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let values = vec![1u32, 2, 3];
+
+    q!(Vars { values }, {
+        fn output_repeat_sep() -> u32 {
+            @for (values) sep(+) { values }
+        }
+    })
+    .into()
+}
+
+#[proc_macro]
+pub fn conditional(_: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let yes = true;
+
+    q!(Vars {}, {
+        fn output_conditional_if() -> &'static str {
+            @if (yes) { "then" } @else { "else" }
+        }
+
+        fn output_conditional_else() -> &'static str {
+            @if (!yes) { "then" } @else { "else" }
+        }
+
+        fn output_conditional_no_else() -> Option<&'static str> {
+            let mut ret = None;
+            @if (!yes) {
+                ret = Some("then");
+            }
+            ret
+        }
+    })
+    .into()
+}
+
+#[proc_macro]
+pub fn interpolation(_: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    q!(Vars { value: "var" }, {
+        fn output_interpolation_expr() -> &'static str {
+            @{ format!("{}-{}", "inline", "expr") }
+        }
+
+        fn output_interpolation_raw() -> &'static str {
+            let @raw(value) = "raw";
+            @raw(value)
+        }
+    })
+    .into()
+}
+
+#[proc_macro]
+pub fn span_override(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    q!(input.first_last() => Vars {}, {
+        fn output_span_override() -> &'static str {
+            @span(Span::call_site()) {
+                "foo"
+            }
+        }
+    })
+    .into()
+}
+
+#[proc_macro]
+pub fn mixed_site(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    Quote::new_mixed_site()
+        .quote_with(smart_quote!(Vars { input }, {
+            {
+                let tmp = 1;
+                tmp + input
+            }
+        }))
+        .into()
+}
+
+#[proc_macro]
+pub fn proc_macro_template(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+    let names = vec!["a", "b", "c"];
+    let values = vec![1u32, 2, 3];
+    let first_last = input.first_last();
+
+    Quote::new_call_site()
+        .quote_with(pmutil_macros::smart_quote!(input.first_last() => Vars {
+            name: &names,
+            value: &values,
+            input,
+        }, {
+            fn output_proc_macro_names() -> &'static [&'static str] {
+                &[@for (name) sep(,) { name }]
+            }
+
+            fn output_proc_macro_sum() -> u32 {
+                0 @for (value) { + value }
+            }
+
+            fn output_proc_macro_input() -> u32 {
+                let @raw(input) = input;
+                @if (names.len() > 5) {
+                    0
+                } @else {
+                    @span(Span::call_site()) { @raw(input) }
+                }
+            }
+
+            fn output_proc_macro_lifetime<'a>(s: &'a str) -> &'a str {
+                @{ pmutil::syn::Ident::new("s", Span::call_site()) }
+            }
+        }))
+        // Span expression which starts with a group.
+        .quote_with(
+            pmutil_macros::smart_quote!((first_last).clone() => Vars {}, {
+                fn output_proc_macro_paren_span() -> u32 {
+                    1
+                }
+            }),
+        )
+        .quote_with(smart_quote!((first_last).clone() => Vars {}, {
+            fn output_rules_paren_span() -> u32 {
+                1
+            }
+        }))
+        .into()
+}
+
+/// Returns source text of each generated token, or `?` for tokens at call
+/// site.
+fn source_texts(tokens: proc_macro2::TokenStream) -> String {
+    let call_site = Span::call_site().source_text();
+
+    tokens
+        .into_iter()
+        .map(|tt| match tt.span().source_text() {
+            Some(ref text) if Some(text) != call_site.as_ref() => text.clone(),
+            _ => "?".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[proc_macro]
+pub fn sequential_spans(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    let q = Quote::new(Sequential::from_tokens(&input)).quote_with(smart_quote!(Vars {}, {
+        w x y z
+    }));
+    let s = source_texts(q.into());
+    q!(Vars { s }, { s }).into()
+}
+
+#[proc_macro]
+pub fn cycle_spans(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    let q = Quote::new(Cycle::from_tokens(&input)).quote_with(smart_quote!(Vars {}, {
+        w x y z
+    }));
+    let s = source_texts(q.into());
+    q!(Vars { s }, { s }).into()
+}
+
+#[proc_macro]
+pub fn by_ident_spans(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    let q = Quote::new(ByIdent::from_tokens(
+        &input,
+        respan::from_fn(Span::call_site),
+    ))
+    .quote_with(smart_quote!(Vars {}, { b + a + c }));
+    let s = source_texts(q.into());
+    q!(Vars { s }, { s }).into()
+}
+
+#[proc_macro]
+pub fn span_override_spans(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    let q = q!(input.first_last() => Vars {}, {
+        w @span(Span::call_site()) { x y } z
+    });
+    let s = source_texts(q.into());
+    q!(Vars { s }, { s }).into()
+}
+
+#[proc_macro]
+pub fn respan_call_site_spans(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    let mut tokens = q!({ c }).dump();
+    tokens.extend(input.clone());
+
+    let all = respan::respan_tokens(tokens.clone(), &Cycle::from_tokens(&input));
+    let call_site = respan::respan_call_site(tokens, &Cycle::from_tokens(&input));
+
+    let all = source_texts(all);
+    let call_site = source_texts(call_site);
+    q!(Vars { all, call_site }, { (all, call_site) }).into()
+}
+
+#[proc_macro]
+pub fn respan_node_spans(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    let mut path: pmutil::syn::Path = pmutil::syn::parse_quote!(x::y);
+    respan::respan_node(&mut path, &Sequential::from_tokens(&input)).unwrap();
+
+    let s = source_texts(path.dump());
+    q!(Vars { s }, { s }).into()
+}
+
+#[proc_macro]
+pub fn site_checks(_: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let call_site = Span::call_site();
+    let mixed_site = Span::mixed_site();
+
+    let checks = [
+        call_site.is_call_site(),
+        call_site.is_mixed_site(),
+        mixed_site.is_call_site(),
+        mixed_site.is_mixed_site(),
+    ];
+    q!(Vars { checks: &checks[..] }, { [@for (checks) sep (,) { checks }] }).into()
+}
+
+#[proc_macro]
+pub fn span_range_spans(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    let q = Quote::new(input.span_range()).quote_with(smart_quote!(Vars {}, { x y z }));
+    let s = source_texts(q.into());
+    q!(Vars { s }, { s }).into()
+}
+
+#[proc_macro]
+pub fn deprecated_key(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+
+    let mut q = q!({
+        fn output_deprecated_key() -> &'static str {
+            "ok"
+        }
+    });
+    let mut errors = pmutil::diag::Errors::new();
+    errors.push(
+        pmutil::diag::Diagnostic::spanned(
+            pmutil::diag::Level::Warning,
+            &input,
+            "this key is deprecated",
+        )
+        .help("use `new_key` instead"),
+    );
+    q.push_errors(errors);
+    q.into()
+}
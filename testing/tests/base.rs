@@ -27,3 +27,38 @@ expanded_fn_like!();
 fn test_expanded_fn_like() {
     assert_eq!(output_expanded_fn_like(), "foo");
 }
+
+#[test]
+fn test_token_kinds() {
+    let q = pmutil::q!({
+        fn r#type<'a>(x: &'a u8) -> bool {
+            x >>= 1_u8;
+            *x == b'a' || "s" != r"r" || -1.5 < 2
+        }
+    });
+
+    assert_eq!(
+        proc_macro2::TokenStream::from(q).to_string(),
+        "fn r#type < 'a > (x : & 'a u8) -> bool { x >>= 1_u8 ; * x == b'a' || \"s\" != r\"r\" || - 1.5 < 2 }"
+    );
+}
+
+#[test]
+fn test_literal_kinds() {
+    let q = pmutil::q!({ ('c', '\n', "s", "a\"b", 18446744073709551615, 007, 1e3, b"x") });
+
+    assert_eq!(
+        proc_macro2::TokenStream::from(q).to_string(),
+        "('c' , '\\n' , \"s\" , \"a\\\"b\" , 18446744073709551615 , 007 , 1e3 , b\"x\")"
+    );
+}
+
+#[test]
+fn test_literal_larger_than_u128() {
+    let q = pmutil::q!({ 340282366920938463463374607431768211456 });
+
+    assert_eq!(
+        proc_macro2::TokenStream::from(q).to_string(),
+        "340282366920938463463374607431768211456"
+    );
+}
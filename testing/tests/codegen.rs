@@ -0,0 +1,54 @@
+use pmutil::prelude::*;
+use std::{env, fs};
+
+fn foo() -> Quote {
+    q!({
+        pub struct Foo;
+    })
+}
+
+#[test]
+fn test_write_rust_file() {
+    let dir = concat!(env!("CARGO_TARGET_TMPDIR"), "/pmutil-codegen");
+    let _ = fs::remove_dir_all(dir);
+    env::set_var("OUT_DIR", dir);
+
+    let path = foo().write_rust_file("foo.rs").unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        format!(
+            "// @generated by pmutil from {}:5:5. Do not edit.\n\npub struct Foo;\n",
+            file!()
+        )
+    );
+
+    // Not changed
+    assert!(!foo().write_rust_file_at(&path).unwrap());
+    // Changed
+    assert!(q!({
+        pub struct Bar;
+    })
+    .write_rust_file_at(&path)
+    .unwrap());
+    // Not a file
+    assert!(q!({ pub struct }).write_rust_file_at(&path).is_err());
+}
+
+#[test]
+fn test_write_rust_file_comparison() {
+    let dir = concat!(env!("CARGO_TARGET_TMPDIR"), "/pmutil-codegen-comparison");
+    let _ = fs::remove_dir_all(dir);
+    let path = format!("{}/cmp.rs", dir);
+
+    q!({
+        fn f(a: i32, b: i32) -> bool {
+            a < -b
+        }
+    })
+    .write_rust_file_at(&path)
+    .unwrap();
+
+    let content = fs::read_to_string(&path).unwrap();
+    assert!(content.contains("a < -b"), "{}", content);
+    pmutil::syn::parse_file(&content).unwrap();
+}
@@ -0,0 +1,10 @@
+use testing::*;
+
+conditional!();
+
+#[test]
+fn test_conditional() {
+    assert_eq!(output_conditional_if(), "then");
+    assert_eq!(output_conditional_else(), "else");
+    assert_eq!(output_conditional_no_else(), None);
+}
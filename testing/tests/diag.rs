@@ -0,0 +1,99 @@
+use pmutil::diag::{Diagnostic, Errors, Level};
+use pmutil::prelude::*;
+use pmutil::syn;
+use proc_macro2::{Span, TokenStream};
+
+#[test]
+fn test_error() {
+    let tokens = Diagnostic::error(Span::call_site(), "foo").emit();
+
+    assert_eq!(tokens.to_string(), ":: core :: compile_error ! { \"foo\" }");
+}
+
+#[test]
+fn test_notes() {
+    let tokens = Diagnostic::error(Span::call_site(), "foo")
+        .note("bar")
+        .span_help(Span::call_site(), "baz")
+        .emit();
+
+    assert_eq!(
+        tokens.to_string(),
+        ":: core :: compile_error ! { \"foo\\n\\nnote: bar\\nhelp: baz\" } \
+         :: core :: compile_error ! { \"help: baz\" }"
+    );
+}
+
+#[test]
+fn test_spanned() {
+    let input: TokenStream = "first\nsecond".parse().unwrap();
+    let tokens = Diagnostic::spanned(Level::Error, &input, "foo").emit();
+    let tts = tokens.into_iter().collect::<Vec<_>>();
+
+    tts[0].span().assert_line(1);
+    tts[7].span().assert_line(2);
+}
+
+#[test]
+fn test_warning() {
+    let tokens = pmutil::diag::warning(Span::call_site(), "foo");
+
+    assert_eq!(
+        tokens.to_string(),
+        "const _ : () = { # [deprecated (note = \"foo\")] const fn warning () { } warning () ; } ;"
+    );
+}
+
+fn count_errors(tokens: TokenStream) -> usize {
+    tokens
+        .into_iter()
+        .filter(|tt| tt.to_string() == "compile_error")
+        .count()
+}
+
+#[test]
+fn test_errors() {
+    let mut errors = Errors::new();
+    let types = ["u8", "<", "Vec<u8>", "&"]
+        .iter()
+        .filter_map(|s| errors.ok_or_push(syn::parse_str::<syn::Type>(s)))
+        .collect::<Vec<_>>();
+    errors.push(Diagnostic::error(Span::call_site(), "foo"));
+    errors.push(Diagnostic::warning(Span::call_site(), "bar"));
+
+    assert_eq!(types.len(), 2);
+    assert!(errors.has_errors());
+    assert_eq!(count_errors(errors.finish().unwrap_err()), 3);
+}
+
+#[test]
+fn test_errors_empty() {
+    let mut errors = Errors::new();
+    assert!(errors.is_empty());
+
+    errors.push(Diagnostic::warning(Span::call_site(), "bar"));
+
+    assert!(!errors.is_empty());
+    assert!(!errors.has_errors());
+    let warnings = errors.finish().unwrap();
+    assert_eq!(
+        warnings.to_string(),
+        "const _ : () = { # [deprecated (note = \"bar\")] const fn warning () { } warning () ; } ;"
+    );
+}
+
+#[test]
+fn test_push_errors() {
+    let mut errors = Errors::new();
+    errors.push(syn::Error::new(Span::call_site(), "foo"));
+
+    let mut q = q!({
+        struct Foo;
+    });
+    q.push_errors(errors);
+
+    assert_eq!(
+        TokenStream::from(q).to_string(),
+        "struct Foo ; :: core :: compile_error ! { \"foo\" }"
+    );
+}
@@ -0,0 +1,36 @@
+use pmutil::prelude::*;
+use pmutil::syn;
+use std::{env, fs};
+
+#[test]
+fn test_dump_dir() {
+    let dir = concat!(env!("CARGO_TARGET_TMPDIR"), "/pmutil-dump");
+    let _ = fs::remove_dir_all(dir);
+    env::set_var("PMUTIL_DUMP_DIR", dir);
+
+    let line = line!() + 1;
+    let q = q!({
+        struct Foo {
+            a: u8,
+        }
+    })
+    .for_input(&"input");
+    let pretty = q.to_pretty_string();
+    q.try_parse::<syn::DeriveInput>().unwrap();
+
+    let prefix = format!("{}-{}-", file!().replace(['/', '\\', '.'], "_"), line);
+    let files = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+
+    assert_eq!(files.len(), 1);
+    let name = files[0].file_name().unwrap().to_str().unwrap();
+    assert!(
+        name.starts_with(&prefix),
+        "{} should start with {}",
+        name,
+        prefix
+    );
+    assert_eq!(fs::read_to_string(&files[0]).unwrap(), pretty + "\n");
+}
@@ -0,0 +1,81 @@
+use pmutil::prelude::*;
+use pmutil::syn;
+use proc_macro2::{Delimiter, TokenStream};
+use std::panic::{self, AssertUnwindSafe};
+
+#[test]
+fn test_panic_into_compile_error() {
+    let tokens: TokenStream = q!({
+        struct Foo;
+    })
+    .quote_with(|_| panic!("quote_with panicked"))
+    .into();
+    let tokens = tokens.to_string();
+
+    assert!(tokens.contains("compile_error"));
+    assert!(tokens.contains("quote_with panicked"));
+    assert!(!tokens.contains("Foo"));
+}
+
+#[test]
+fn test_check_into_compile_error() {
+    let tokens: TokenStream = q!({ struct }).check::<syn::Type>().into();
+
+    assert!(tokens.to_string().contains("compile_error"));
+}
+
+#[test]
+fn test_check_valid() {
+    let tokens: TokenStream = q!({ Vec<u8> }).check::<syn::Type>().into();
+
+    assert_eq!(tokens.to_string(), "Vec < u8 >");
+}
+
+#[test]
+fn test_panic_discards_only_new_tokens() {
+    let q = q!({
+        struct Foo;
+    })
+    .quote_with(|q| {
+        q.push_ident("Bar");
+        panic!("quote_with panicked")
+    });
+
+    assert_eq!(q.to_pretty_string(), "struct Foo;");
+}
+
+#[test]
+fn test_into_iter_emits_errors() {
+    let q = q!({ Vec<u8> }).quote_with(|_| panic!("quote_with panicked"));
+    let tokens = q.into_iter().collect::<TokenStream>().to_string();
+
+    assert!(tokens.contains("compile_error"));
+    assert!(!tokens.contains("Vec"));
+}
+
+#[test]
+fn test_with_span_restores_span_on_panic() {
+    let input: TokenStream = "foo".parse().unwrap();
+    let mut q = Quote::new_call_site();
+
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        q.with_span(input.first_last(), |_| panic!("with_span panicked"))
+    }));
+    assert!(res.is_err());
+
+    q.push_ident("bar");
+    let tt = TokenStream::from(q).into_iter().next().unwrap();
+    assert!(tt.span().is_call_site());
+}
+
+#[test]
+fn test_panic_in_group_keeps_span() {
+    let input: TokenStream = "\n\nfoo".parse().unwrap();
+    let q = Quote::new(input.first_last()).quote_with(|q| {
+        q.push_ident("impl");
+        q.push_group(Delimiter::Brace, |_| panic!("quote_with panicked"))
+    });
+
+    let tt = TokenStream::from(q).into_iter().next().unwrap();
+    assert_eq!(tt.span().location().to_string(), "3:0..3:3");
+}
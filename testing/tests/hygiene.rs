@@ -0,0 +1,8 @@
+use testing::*;
+
+#[test]
+fn test_mixed_site() {
+    let tmp = 10;
+
+    assert_eq!(mixed_site!(tmp), 11);
+}
@@ -0,0 +1,9 @@
+use testing::*;
+
+interpolation!();
+
+#[test]
+fn test_interpolation() {
+    assert_eq!(output_interpolation_expr(), "inline-expr");
+    assert_eq!(output_interpolation_raw(), "raw");
+}
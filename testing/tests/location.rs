@@ -0,0 +1,79 @@
+use pmutil::prelude::*;
+use pmutil::respan::Sequential;
+use proc_macro2::TokenStream;
+
+fn input() -> TokenStream {
+    "first\nsecond(\n    third\n)".parse().unwrap()
+}
+
+#[test]
+fn test_location() {
+    let input = input();
+    let loc = input.clone().into_iter().nth(1).unwrap().span().location();
+
+    assert_eq!(loc.to_string(), "2:0..2:6");
+}
+
+#[test]
+fn test_first_last_lines() {
+    let q = Quote::new(input().first_last()).quote_with(smart_quote!(Vars {}, { a b c }));
+    let tts = TokenStream::from(q).into_iter().collect::<Vec<_>>();
+
+    tts[0].span().assert_line(1);
+    tts[1].span().assert_line(2);
+    tts[2].span().assert_line(2);
+}
+
+#[test]
+fn test_sequential_lines() {
+    let q = Quote::new(Sequential::from_tokens(&input()))
+        .quote_with(smart_quote!(Vars {}, { a (b c) d }));
+    let tts = TokenStream::from(q).into_iter().collect::<Vec<_>>();
+
+    tts[0].span().assert_line(1);
+    tts[1].span().assert_line(2);
+    match tts[1] {
+        proc_macro2::TokenTree::Group(ref g) => {
+            let inner = g.stream().into_iter().collect::<Vec<_>>();
+            // Span of `(` in input.
+            inner[0].span().assert_line(2);
+            inner[1].span().assert_line(3);
+        }
+        _ => unreachable!(),
+    }
+    tts[2].span().assert_line(3);
+}
+
+#[test]
+#[should_panic(expected = "expected span at line 2, but it's at 1:0..1:5")]
+fn test_assert_line_fails() {
+    input().into_iter().next().unwrap().span().assert_line(2);
+}
+
+#[test]
+fn test_dump_spans() {
+    let source = "first\nsecond(\n    third\n)";
+    let input: TokenStream = source.parse().unwrap();
+
+    let q = Quote::new(Sequential::from_tokens(&input))
+        .quote_with(smart_quote!(Vars {}, { a (b c) }))
+        .quote_with(smart_quote!(proc_macro2::Span::call_site() => Vars {}, { d }));
+
+    assert_eq!(
+        q.dump_spans(Some(source)),
+        "\
+a    1:0..1:5
+    1 | first
+      | ^^^^^
+(    2:0..2:6
+    2 | second(
+      | ^^^^^^
+  b  2:6..4:1
+    2 | second(
+      |       ^
+  c  3:4..3:9
+    3 |     third
+      |     ^^^^^
+d    <call_site>"
+    );
+}
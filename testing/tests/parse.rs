@@ -0,0 +1,51 @@
+use pmutil::prelude::*;
+use pmutil::syn;
+
+#[test]
+fn test_try_parse() {
+    let ty: syn::Type = q!({ Vec<u8> }).try_parse().unwrap();
+
+    assert!(matches!(ty, syn::Type::Path(..)));
+}
+
+#[test]
+fn test_try_parse_error_notes_location() {
+    let err = match q!({ struct }).try_parse::<syn::Type>() {
+        Ok(..) => panic!("`struct` should not be parsed as a type"),
+        Err(err) => err,
+    };
+
+    assert!(err.to_string().contains(file!()));
+}
+
+#[test]
+fn test_try_parse_error_notes_exact_location() {
+    let first = line!() + 3;
+    let second = line!() + 3;
+    let res = Quote::new_call_site()
+        .quote_with(smart_quote!({ Vec<u8> }))
+        .quote_with(smart_quote!({ struct }))
+        .try_parse::<syn::Type>();
+    let err = match res {
+        Ok(..) => panic!("`Vec<u8> struct` should not be parsed as a type"),
+        Err(err) => err.to_string(),
+    };
+
+    assert!(err.contains(&format!("{}:{}:", file!(), second)));
+    assert!(!err.contains(&format!("{}:{}:", file!(), first)));
+}
+
+#[test]
+fn test_try_parse_error_from_pushed_tokens() {
+    let line = line!() + 1;
+    let mut q = Quote::new_call_site().quote_with(smart_quote!({ Vec<u8> }));
+    let user_tokens: proc_macro2::TokenStream = "struct".parse().unwrap();
+    q.push_tokens(&user_tokens);
+
+    let err = match q.try_parse::<syn::Type>() {
+        Ok(..) => panic!("`Vec<u8> struct` should not be parsed as a type"),
+        Err(err) => err.to_string(),
+    };
+
+    assert!(!err.contains(&format!("{}:{}:", file!(), line)));
+}
@@ -0,0 +1,73 @@
+use pmutil::prelude::*;
+
+#[test]
+fn test_pretty_item() {
+    let q = q!({
+        #[derive(Debug)]
+        struct Foo<T: Clone> {
+            a: Vec<T>,
+            b: &'static str,
+        }
+        impl<T: Clone> Foo<T> {
+            fn new(a: Vec<T>) -> Self {
+                if a.is_empty() {
+                    panic!()
+                } else {
+                    Foo { a, b: "" }
+                }
+            }
+        }
+    });
+
+    assert_eq!(
+        q.to_pretty_string(),
+        r#"#[derive(Debug)]
+struct Foo<T: Clone> {
+    a: Vec<T>,
+    b: &'static str,
+}
+impl<T: Clone> Foo<T> {
+    fn new(a: Vec<T>) -> Self {
+        if a.is_empty() {
+            panic!()
+        } else {
+            Foo {
+                a,
+                b: ""
+            }
+        }
+    }
+}"#
+    );
+}
+
+#[test]
+fn test_pretty_fragment() {
+    let tokens = q!({ where T: Into<Vec<u8>>, }).dump();
+
+    assert_eq!(tokens.to_pretty_string(), "where T: Into<Vec<u8>>,");
+}
+
+#[test]
+fn test_pretty_comparison() {
+    let tokens = q!({ x < y && a < -b }).dump();
+
+    assert_eq!(tokens.to_pretty_string(), "x < y && a < -b");
+}
+
+#[test]
+fn test_pretty_generics_after_path() {
+    let tokens = q!({
+        fn f<T>() {
+            iter.collect::<Vec<T>>()
+        }
+    })
+    .dump();
+
+    assert_eq!(
+        tokens.to_pretty_string(),
+        "fn f<T>() {
+    iter.collect::<Vec<T>>()
+}"
+    );
+}
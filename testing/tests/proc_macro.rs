@@ -0,0 +1,12 @@
+use testing::*;
+
+proc_macro_template!(7);
+
+#[test]
+fn test_proc_macro() {
+    assert_eq!(output_proc_macro_names(), &["a", "b", "c"]);
+    assert_eq!(output_proc_macro_sum(), 6);
+    assert_eq!(output_proc_macro_input(), 7);
+    assert_eq!(output_proc_macro_lifetime("foo"), "foo");
+    assert_eq!(output_proc_macro_paren_span(), output_rules_paren_span());
+}
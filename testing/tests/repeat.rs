@@ -0,0 +1,27 @@
+use testing::*;
+
+repeat!();
+
+#[test]
+fn test_repeat() {
+    assert_eq!(output_repeat_a(), "foo");
+    assert_eq!(output_repeat_b(), "bar");
+}
+
+repeat_sep!();
+
+#[test]
+fn test_repeat_sep() {
+    assert_eq!(output_repeat_sep(), 6);
+}
+
+#[test]
+fn test_push_separated() {
+    let mut q = pmutil::Quote::new_call_site();
+    q.push_separated(&["a", "b"], ",");
+
+    assert_eq!(
+        proc_macro2::TokenStream::from(q).to_string(),
+        "\"a\" , \"b\""
+    );
+}
@@ -0,0 +1,164 @@
+use pmutil::prelude::*;
+use pmutil::respan::{self, Respan};
+use proc_macro2::{Delimiter, Span};
+use std::cell::Cell;
+use std::rc::Rc;
+use testing::*;
+
+span_override!(input);
+
+#[test]
+fn test_span_override() {
+    assert_eq!(output_span_override(), "foo");
+    assert_eq!(span_override_spans!(a b), "a ? ? b");
+}
+
+/// Counts spans requested by `Quote`.
+struct Counter(Rc<Cell<usize>>);
+
+impl Respan for Counter {
+    fn next_span(&self) -> Span {
+        self.0.set(self.0.get() + 1);
+        Span::call_site()
+    }
+}
+
+#[test]
+fn test_group_span_from_strategy() {
+    let count = Rc::new(Cell::new(0));
+
+    let q = Quote::new(Counter(count.clone())).quote_with(smart_quote!(Vars {}, {
+        fn foo() {
+            [1]
+        }
+    }));
+
+    assert_eq!(
+        proc_macro2::TokenStream::from(q).to_string(),
+        "fn foo () { [1] }"
+    );
+    assert_eq!(count.get(), 6);
+}
+
+#[test]
+fn test_boxed_strategy() {
+    let count = Rc::new(Cell::new(0));
+
+    let q = Quote::new(Box::new(Counter(count.clone()))).quote_with(smart_quote!({ a b }));
+    assert_eq!(proc_macro2::TokenStream::from(q).to_string(), "a b");
+    assert_eq!(count.get(), 2);
+
+    let input: proc_macro2::TokenStream = "x y".parse().unwrap();
+    let q = Quote::new(Box::new(input.first_last())).quote_with(smart_quote!({ a b }));
+    assert_eq!(proc_macro2::TokenStream::from(q).to_string(), "a b");
+}
+
+#[test]
+fn test_from_fn() {
+    let count = Rc::new(Cell::new(0));
+    let counter = count.clone();
+
+    let q = Quote::new(respan::from_fn(move || {
+        counter.set(counter.get() + 1);
+        Span::call_site()
+    }))
+    .quote_with(smart_quote!({ a b }));
+    assert_eq!(proc_macro2::TokenStream::from(q).to_string(), "a b");
+    assert_eq!(count.get(), 2);
+}
+
+#[test]
+fn test_push_group_spanned() {
+    let count = Rc::new(Cell::new(0));
+
+    let mut q = Quote::new(Counter(count.clone()));
+    q.push_group_spanned(Delimiter::Brace, Span::call_site(), |q| q.push_ident("a"));
+
+    assert_eq!(proc_macro2::TokenStream::from(q).to_string(), "{ a }");
+    assert_eq!(count.get(), 1);
+}
+
+#[test]
+fn test_respan_var() {
+    let count = Rc::new(Cell::new(0));
+    let path: proc_macro2::TokenStream = "a::b(c)".parse().unwrap();
+
+    let q = Quote::new(Counter(count.clone())).quote_with(smart_quote!(
+        Vars {
+            #[respan]
+            path: &path,
+            plain: &path,
+        },
+        {
+            let x = path + plain;
+        }
+    ));
+
+    assert_eq!(
+        proc_macro2::TokenStream::from(q).to_string(),
+        "let x = a :: b (c) + a :: b (c) ;"
+    );
+    // 5 tokens of the template and 6 tokens of `path`.
+    assert_eq!(count.get(), 11);
+}
+
+#[test]
+fn test_sequential() {
+    assert_eq!(sequential_spans!(a b c), "a b c c");
+}
+
+#[test]
+fn test_cycle() {
+    assert_eq!(cycle_spans!(a b c), "a b c a");
+}
+
+#[test]
+fn test_by_ident() {
+    assert_eq!(by_ident_spans!(a b), "b ? a ? ?");
+}
+
+#[test]
+fn test_respan_tokens() {
+    assert_eq!(respan_call_site_spans!(a b), ("a b a", "a a b"));
+}
+
+#[test]
+fn test_respan_node() {
+    assert_eq!(respan_node_spans!(a b), "a b b b");
+}
+
+/// Prints `x`, but cannot parse it.
+struct Unparsable;
+
+impl pmutil::syn::parse::Parse for Unparsable {
+    fn parse(input: pmutil::syn::parse::ParseStream) -> pmutil::syn::Result<Self> {
+        Err(input.error("unparsable"))
+    }
+}
+
+impl pmutil::quote::ToTokens for Unparsable {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        tokens.extend(q!({ x }).dump());
+    }
+}
+
+#[test]
+fn test_respan_node_error() {
+    let err = respan::respan_node(&mut Unparsable, &Span::call_site()).unwrap_err();
+
+    assert_eq!(err.to_string(), "unparsable");
+}
+
+#[test]
+fn test_span_range() {
+    if cfg!(feature = "nightly") {
+        assert_eq!(span_range_spans!(a + b), "a + b a + b a + b");
+    } else {
+        assert_eq!(span_range_spans!(a + b), "a b b");
+    }
+}
+
+#[test]
+fn test_site_checks() {
+    assert_eq!(site_checks!(), [true, false, false, true]);
+}
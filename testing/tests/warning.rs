@@ -0,0 +1,11 @@
+// `deprecated_key!` emits a warning on purpose.
+#![allow(deprecated)]
+
+use testing::*;
+
+deprecated_key!(old_key);
+
+#[test]
+fn test_warning() {
+    assert_eq!(output_deprecated_key(), "ok");
+}